@@ -0,0 +1,151 @@
+use std::{collections::VecDeque, error::Error};
+
+use intcode::intcode::{Program, ProgramState};
+
+use crate::{palette::Palette, Color, Turn};
+
+/// Decides what the robot paints and where it goes.
+pub trait RobotBrain {
+    /// The colour to paint the panel below the robot, currently of colour `current`, and where to
+    /// turn before moving on. `None` stops the robot.
+    fn step(&mut self, current: Color) -> Result<Option<(Color, Turn)>, Box<dyn Error + 'static>>;
+}
+
+/// The emergency hull painting program, reading and writing colours as the values of `palette`.
+pub struct IntcodeBrain {
+    prog: Program,
+    palette: Palette,
+}
+
+impl IntcodeBrain {
+    pub fn new(memory: Vec<isize>, palette: Palette) -> Self {
+        IntcodeBrain { prog: Program::new(memory), palette }
+    }
+
+    pub fn parse(input: &str, palette: Palette) -> Result<Self, Box<dyn Error + 'static>> {
+        let memory: Result<Vec<isize>, _> = input.split(',')
+            .map(|part| part.trim().parse::<isize>())
+            .collect();
+        Ok(IntcodeBrain::new(memory?, palette))
+    }
+}
+
+impl RobotBrain for IntcodeBrain {
+    fn step(&mut self, current: Color) -> Result<Option<(Color, Turn)>, Box<dyn Error + 'static>> {
+        let cur_color = self.palette.paint(current).value;
+
+        let result = match self.prog.run(&mut Some(cur_color)) {
+            (ProgramState::Halt, _) => return Ok(None),
+            (ProgramState::Crash, _) => {
+                let report = self.prog.crash_report().expect("crashed program has a report");
+                return Err(format!("The painting program crashed\n{}", report).into());
+            }
+            (_, result) => result,
+        };
+
+        if result.len() != 2 {
+            return Err(format!("Expected a colour and a turn from the program, got {:?}", result).into());
+        }
+        let color = self.palette.color_of_value(result[0])
+            .ok_or_else(|| format!("Unknown colour {}", result[0]))?;
+        let turn = match result[1] {
+            0 => Turn::Left,
+            1 => Turn::Right,
+            x => return Err(format!("Unknown turn {}", x).into()),
+        };
+        Ok(Some((color, turn)))
+    }
+}
+
+/// Langton's ant: turns right on black panels and left on white ones, flipping the colour of
+/// every panel it leaves. Panels of other colours count as black. Stops after a fixed number of steps.
+pub struct LangtonsAnt {
+    steps: usize,
+}
+
+impl LangtonsAnt {
+    pub fn new(steps: usize) -> Self {
+        LangtonsAnt { steps }
+    }
+}
+
+impl RobotBrain for LangtonsAnt {
+    fn step(&mut self, current: Color) -> Result<Option<(Color, Turn)>, Box<dyn Error + 'static>> {
+        if self.steps == 0 {
+            return Ok(None);
+        }
+        self.steps -= 1;
+        Ok(Some(match current {
+            Color::WHITE => (Color::BLACK, Turn::Left),
+            _ => (Color::WHITE, Turn::Right),
+        }))
+    }
+}
+
+/// Plays back fixed moves regardless of the colours, then stops.
+pub struct Scripted(VecDeque<(Color, Turn)>);
+
+impl Scripted {
+    pub fn new(moves: Vec<(Color, Turn)>) -> Self {
+        Scripted(moves.into())
+    }
+}
+
+impl RobotBrain for Scripted {
+    fn step(&mut self, _: Color) -> Result<Option<(Color, Turn)>, Box<dyn Error + 'static>> {
+        Ok(self.0.pop_front())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{paint, HullMap, RobotPosition};
+
+    #[test]
+    fn test_intcode() {
+        // Paints black panels white and white panels black, turning left on black.
+        let mut brain = IntcodeBrain::parse("3,20,1008,20,0,21,4,21,4,20,1105,1,0", Palette::default()).unwrap();
+        assert_eq!(brain.step(Color::BLACK).unwrap(), Some((Color::WHITE, Turn::Left)));
+        assert_eq!(brain.step(Color::WHITE).unwrap(), Some((Color::BLACK, Turn::Right)));
+
+        let mut brain = IntcodeBrain::parse("3,9,104,2,104,0,1105,1,0", Palette::default()).unwrap();
+        assert!(brain.step(Color::BLACK).is_err());
+        let mut brain = IntcodeBrain::parse("3,9,104,1,3,9", Palette::default()).unwrap();
+        assert!(brain.step(Color::BLACK).is_err());
+        let mut brain = IntcodeBrain::parse("99", Palette::default()).unwrap();
+        assert_eq!(brain.step(Color::BLACK).unwrap(), None);
+        let mut brain = IntcodeBrain::parse("3,9,42", Palette::default()).unwrap();
+        assert!(brain.step(Color::BLACK).unwrap_err().to_string().contains("Unknown instruction 42"));
+
+        // Paints every panel in the colour it already has, turning left.
+        let palette = Palette::parse("0 black . 000000\n1 white # ffffff\n5 red R ff0000").unwrap();
+        let mut brain = IntcodeBrain::parse("3,20,4,20,104,0,1105,1,0", palette).unwrap();
+        assert_eq!(brain.step(Color(2)).unwrap(), Some((Color(2), Turn::Left)));
+        assert_eq!(brain.step(Color::WHITE).unwrap(), Some((Color::WHITE, Turn::Left)));
+    }
+
+    #[test]
+    fn test_langtons_ant() {
+        let (hull, steps) = paint(&mut LangtonsAnt::new(5), HullMap::default()).unwrap();
+        assert_eq!(steps.len(), 5);
+        assert_eq!(hull.to_string(), " #\n##\n");
+        assert_eq!(*hull.get_color(RobotPosition(0, 0)), Color::BLACK);
+        assert_eq!(steps[4].read, Color::WHITE);
+        assert_eq!(steps[4].next().0, RobotPosition(-1, 0));
+
+        // The ant builds its highway after about 10000 steps, leaving over 1000 panels painted.
+        let (hull, _) = paint(&mut LangtonsAnt::new(11000), HullMap::default()).unwrap();
+        assert!(hull.len() > 1000);
+    }
+
+    #[test]
+    fn test_scripted() {
+        let moves = vec![(Color::WHITE, Turn::Left), (Color::WHITE, Turn::Left), (Color::WHITE, Turn::Right)];
+        let (hull, steps) = paint(&mut Scripted::new(moves), HullMap::start(Color::WHITE)).unwrap();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].read, Color::WHITE);
+        assert_eq!(hull.to_string(), "##\n# \n");
+        assert_eq!(hull.len(), 3);
+    }
+}
@@ -0,0 +1,236 @@
+use std::{error::Error, fs, path::Path};
+
+use crate::{
+    brain::{IntcodeBrain, LangtonsAnt, RobotBrain},
+    export::{self, parse_rgb, Rgb, Style},
+    paint,
+    palette::Palette,
+    path::{self, Frame, FrameFormat, Step},
+    stats::Stats,
+    Color,
+    HullMap,
+};
+
+const USAGE: &str = "\
+Usage: p21|p22 [--start black|white | --hull FILE [--origin COL,ROW]] [--palette FILE]
+               [--ant STEPS] [--compare] [--show] [--stats table|json] [--log FILE]
+               [--frames DIR [--format ppm|text] [--every STEPS]]
+               [--text FILE] [--ppm FILE] [--svg FILE] [--cell-size PIXELS] [--black COLOR]
+               [--white COLOR] [--unpainted COLOR] [--robot COLOR]
+
+    --start COLOR       Colour of the start panel (default: black for p21, white for p22).
+                        Starting on black prints the number of painted panels instead of the
+                        registration identifier.
+    --hull FILE         Start on the hull picture in FILE instead of a single painted panel, as
+                        text or PPM image like the ones written by --text and --ppm. Prints the
+                        number of painted panels.
+    --origin COL,ROW    Panel of the hull picture the robot starts on (default: 0,0, top left).
+    --palette FILE      Colours the robot can paint, one 'VALUE NAME GLYPH RRGGBB' per line,
+                        where VALUE is what the program reads and writes. The first two colours
+                        are black and white.
+    --ant STEPS         Paint with Langton's ant for STEPS steps instead of the Intcode program,
+                        printing the number of painted panels.
+    --compare           Report where the robot's path diverges from a run on the other start colour.
+    --show              Print the painted hull, which is only done by default when reading the
+                        registration identifier.
+    --stats FORMAT      Report statistics of the painting as a 'table' or in 'json'.
+    --log FILE          Write every step of the robot to FILE as CSV.
+    --frames DIR        Write an animation of the robot's path to numbered files in DIR.
+    --format FMT        Format of the animation frames: 'ppm' images (default) or 'text'.
+    --every STEPS       Only write every STEPS-th frame, the last frame is always written.
+    --text FILE         Write the painted hull as text to FILE.
+    --ppm FILE          Write the painted hull as a binary PPM image to FILE.
+    --svg FILE          Write the painted hull as an SVG image to FILE.
+    --cell-size PIXELS  Size of a panel in the images (default: 10).
+    --black COLOR       Colour of panels painted black, as RRGGBB hex (default: 000000).
+    --white COLOR       Colour of panels painted white (default: ffffff).
+    --unpainted COLOR   Colour of panels the robot never painted (default: 5a5a5a).
+    --robot COLOR       Colour of the robot in PPM frames (default: dc2828).";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum StatsFormat {
+    Table,
+    Json,
+}
+
+#[derive(Debug)]
+struct Options {
+    start: Option<Color>,
+    hull: Option<String>,
+    origin: (usize, usize),
+    palette: Option<String>,
+    ant: Option<usize>,
+    compare: bool,
+    show: bool,
+    stats: Option<StatsFormat>,
+    log: Option<String>,
+    frames: Option<String>,
+    format: FrameFormat,
+    every: usize,
+    text: Option<String>,
+    ppm: Option<String>,
+    svg: Option<String>,
+    black: Option<Rgb>,
+    white: Option<Rgb>,
+    style: Style,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item=String>>(mut args: I) -> Result<Self, Box<dyn Error + 'static>> {
+        let mut options = Options {
+            start: None,
+            hull: None,
+            origin: (0, 0),
+            palette: None,
+            ant: None,
+            compare: false,
+            show: false,
+            stats: None,
+            log: None,
+            frames: None,
+            format: FrameFormat::Ppm,
+            every: 1,
+            text: None,
+            ppm: None,
+            svg: None,
+            black: None,
+            white: None,
+            style: Style::default(),
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value for {}\n\n{}", arg, USAGE));
+            match arg.as_str() {
+                "--start" => options.start = match value()?.as_str() {
+                    "black" => Some(Color::BLACK),
+                    "white" => Some(Color::WHITE),
+                    x => return Err(format!("Unknown start colour '{}'", x).into()),
+                },
+                "--hull" => options.hull = Some(value()?),
+                "--origin" => options.origin = match value()?.split(',').map(str::parse).collect::<Result<Vec<_>, _>>()?[..] {
+                    [col, row] => (col, row),
+                    _ => return Err(format!("Expected COL,ROW for --origin\n\n{}", USAGE).into()),
+                },
+                "--palette" => options.palette = Some(value()?),
+                "--ant" => options.ant = Some(value()?.parse()?),
+                "--compare" => options.compare = true,
+                "--show" => options.show = true,
+                "--stats" => options.stats = match value()?.as_str() {
+                    "table" => Some(StatsFormat::Table),
+                    "json" => Some(StatsFormat::Json),
+                    x => return Err(format!("Unknown statistics format '{}'", x).into()),
+                },
+                "--log" => options.log = Some(value()?),
+                "--frames" => options.frames = Some(value()?),
+                "--format" => options.format = match value()?.as_str() {
+                    "ppm" => FrameFormat::Ppm,
+                    "text" => FrameFormat::Text,
+                    x => return Err(format!("Unknown frame format '{}'", x).into()),
+                },
+                "--every" => options.every = value()?.parse()?,
+                "--text" => options.text = Some(value()?),
+                "--ppm" => options.ppm = Some(value()?),
+                "--svg" => options.svg = Some(value()?),
+                "--cell-size" => options.style.cell_size = value()?.parse()?,
+                "--black" => options.black = Some(parse_rgb(&value()?)?),
+                "--white" => options.white = Some(parse_rgb(&value()?)?),
+                "--unpainted" => options.style.unpainted = parse_rgb(&value()?)?,
+                "--robot" => options.style.robot = parse_rgb(&value()?)?,
+                x => return Err(format!("Unknown argument {}\n\n{}", x, USAGE).into()),
+            }
+        }
+        if options.hull.is_some() && (options.start.is_some() || options.compare) {
+            return Err(format!("--hull can't be combined with --start or --compare\n\n{}", USAGE).into());
+        }
+
+        if let Some(file) = &options.palette {
+            options.style.palette = Palette::parse(&fs::read_to_string(file)?)?;
+        }
+        if let Some(rgb) = options.black {
+            options.style.palette.set_rgb(Color::BLACK, rgb);
+        }
+        if let Some(rgb) = options.white {
+            options.style.palette.set_rgb(Color::WHITE, rgb);
+        }
+        Ok(options)
+    }
+
+    /// Whether the hull shows the registration identifier, rather than being painted for the
+    /// panel count or on a loaded picture.
+    fn registration(&self, start: Color) -> bool {
+        start != Color::BLACK && self.hull.is_none() && self.ant.is_none()
+    }
+}
+
+/// Paint the hull with the Intcode program `input` as the command line `args` say, starting on a
+/// panel of colour `start` unless they choose another one.
+pub fn run<I: Iterator<Item=String>>(args: I, input: &str, start: Color) -> Result<(), Box<dyn Error + 'static>> {
+    let options = Options::parse(args)?;
+    let palette = &options.style.palette;
+    let start = options.start.unwrap_or(start);
+    let initial = match &options.hull {
+        Some(file) => export::load(&fs::read(file)?, &options.style, options.origin)?,
+        None => HullMap::start(start),
+    };
+    let (hull, steps) = paint(&mut *brain(&options, input)?, initial.clone())?;
+    if options.show || options.registration(start) {
+        eprintln!("{}", hull.text(palette));
+    }
+
+    if options.compare {
+        let other = if start == Color::BLACK { Color::WHITE } else { Color::BLACK };
+        let (_, other_steps) = paint(&mut *brain(&options, input)?, HullMap::start(other))?;
+        let describe = |steps: &[Step], step: usize| steps.get(step).map_or("halted".to_string(), |step| step.describe(palette));
+        match path::divergence(&steps, &other_steps) {
+            None => eprintln!("Both start colours lead to the same path"),
+            Some(step) => eprintln!(
+                "Path diverges at step {}:\n  {} start: {}\n  {} start: {}",
+                step,
+                palette.paint(start).name, describe(&steps, step),
+                palette.paint(other).name, describe(&other_steps, step),
+            ),
+        }
+    }
+    match options.stats {
+        Some(StatsFormat::Table) => eprint!("{}", Stats::new(&steps, &initial)),
+        Some(StatsFormat::Json) => eprintln!("{}", Stats::new(&steps, &initial).json()),
+        None => (),
+    }
+    if let Some(file) = &options.log {
+        fs::write(file, path::csv(&steps, palette))?;
+    }
+    if let Some(dir) = &options.frames {
+        fs::create_dir_all(dir)?;
+        let (format, style) = (options.format, &options.style);
+        path::animate(&steps, &initial, palette, options.every, |number, frame: &Frame| {
+            let (extension, content) = match format {
+                FrameFormat::Ppm => ("ppm", export::ppm_frame(frame, style)),
+                FrameFormat::Text => ("txt", frame.to_string().into_bytes()),
+            };
+            fs::write(Path::new(dir).join(format!("frame_{:05}.{}", number, extension)), content)
+        })?;
+    }
+
+    if let Some(file) = &options.text {
+        fs::write(file, hull.text(palette))?;
+    }
+    if let Some(file) = &options.ppm {
+        fs::write(file, export::ppm(&hull, &options.style))?;
+    }
+    if let Some(file) = &options.svg {
+        fs::write(file, export::svg(&hull, &options.style))?;
+    }
+    if options.registration(start) {
+        println!("{}", ocr::read(&hull.lit())?);
+    } else {
+        println!("{}", Stats::new(&steps, &initial).panels_painted());
+    }
+    Ok(())
+}
+
+fn brain(options: &Options, input: &str) -> Result<Box<dyn RobotBrain>, Box<dyn Error + 'static>> {
+    Ok(match options.ant {
+        Some(steps) => Box::new(LangtonsAnt::new(steps)),
+        None => Box::new(IntcodeBrain::parse(input, options.style.palette.clone())?),
+    })
+}
+
@@ -0,0 +1,276 @@
+use std::{error::Error, fmt::Write};
+
+use crate::{palette::Palette, path::Frame, Color, HullMap, RobotPosition};
+
+pub type Rgb = [u8; 3];
+
+/// How panels are drawn in exported images.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Style {
+    /// Width and height of a panel in pixels.
+    pub cell_size: usize,
+    pub palette: Palette,
+    /// Panels the robot never painted, which are black as well.
+    pub unpainted: Rgb,
+    /// The robot in animation frames.
+    pub robot: Rgb,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            cell_size: 10,
+            palette: Palette::default(),
+            unpainted: [90, 90, 90],
+            robot: [220, 40, 40],
+        }
+    }
+}
+
+impl Style {
+    fn color(&self, panel: Option<Color>) -> Rgb {
+        match panel {
+            None => self.unpainted,
+            Some(color) => self.palette.paint(color).rgb,
+        }
+    }
+}
+
+/// Parse a colour written as `RRGGBB` hex, optionally prefixed with `#`.
+pub fn parse_rgb(text: &str) -> Result<Rgb, String> {
+    let hex = text.trim_start_matches('#');
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("Invalid colour '{}', expected RRGGBB", text));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    Ok([channel(0), channel(2), channel(4)])
+}
+
+/// Binary PPM image of the painted hull.
+pub fn ppm(hull: &HullMap, style: &Style) -> Vec<u8> {
+    render_ppm(&hull.rows(), None, style)
+}
+
+/// Binary PPM image of an animation frame, with the robot's panel in the robot colour.
+pub fn ppm_frame(frame: &Frame, style: &Style) -> Vec<u8> {
+    render_ppm(&frame.rows(), Some(frame.robot_cell()), style)
+}
+
+fn render_ppm(rows: &[Vec<Option<Color>>], robot: Option<(usize, usize)>, style: &Style) -> Vec<u8> {
+    let size = style.cell_size.max(1);
+    let width = rows.first().map_or(0, |row| row.len()) * size;
+    let mut image = format!("P6\n{} {}\n255\n", width, rows.len() * size).into_bytes();
+    for (y, row) in rows.iter().enumerate() {
+        let mut line = Vec::with_capacity(width * 3);
+        for (x, &panel) in row.iter().enumerate() {
+            let color = if robot == Some((y, x)) { style.robot } else { style.color(panel) };
+            for _ in 0..size {
+                line.extend_from_slice(&color);
+            }
+        }
+        for _ in 0..size {
+            image.extend_from_slice(&line);
+        }
+    }
+    image
+}
+
+/// SVG image of the painted hull, one square per panel on a background of unpainted panels.
+pub fn svg(hull: &HullMap, style: &Style) -> String {
+    let rows = hull.rows();
+    let size = style.cell_size.max(1);
+    let (width, height) = (rows.first().map_or(0, |row| row.len()) * size, rows.len() * size);
+    let hex = |rgb: Rgb| format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]);
+
+    let mut image = String::new();
+    writeln!(
+        image,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" shape-rendering="crispEdges">"#,
+        width, height,
+    ).unwrap();
+    writeln!(image, r#"<rect width="{}" height="{}" fill="{}"/>"#, width, height, hex(style.unpainted)).unwrap();
+    for (y, row) in rows.iter().enumerate() {
+        for (x, panel) in row.iter().enumerate() {
+            if panel.is_some() {
+                writeln!(
+                    image,
+                    r#"<rect x="{0}" y="{1}" width="{2}" height="{2}" fill="{3}"/>"#,
+                    x * size, y * size, size, hex(style.color(*panel)),
+                ).unwrap();
+            }
+        }
+    }
+    image.push_str("</svg>\n");
+    image
+}
+
+/// Load a hull picture written as text or PPM image. Panels are identified by the glyphs or
+/// colours of the style's palette, and are placed so the robot starts on column and row `origin`
+/// of the picture. Black text panels and unpainted image panels are left unpainted.
+pub fn load(picture: &[u8], style: &Style, origin: (usize, usize)) -> Result<HullMap, Box<dyn Error + 'static>> {
+    let rows = if picture.starts_with(b"P6") {
+        ppm_panels(picture, style)?
+    } else {
+        text_panels(std::str::from_utf8(picture)?, &style.palette)?
+    };
+
+    let mut hull = HullMap::default();
+    for (row, panels) in rows.iter().enumerate() {
+        for (col, panel) in panels.iter().enumerate() {
+            if let Some(color) = panel {
+                let position = RobotPosition(col as isize - origin.0 as isize, origin.1 as isize - row as isize);
+                hull.paint(position, *color);
+            }
+        }
+    }
+    Ok(hull)
+}
+
+fn text_panels(text: &str, palette: &Palette) -> Result<Vec<Vec<Option<Color>>>, Box<dyn Error + 'static>> {
+    text.lines()
+        .enumerate()
+        .map(|(row, line)| {
+            line.chars()
+                .enumerate()
+                .map(|(col, glyph)| match palette.color_of_glyph(glyph) {
+                    Some(Color::BLACK) => Ok(None),
+                    Some(color) => Ok(Some(color)),
+                    None => Err(format!("Unknown glyph '{}' at column {} of row {}", glyph, col, row).into()),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn ppm_panels(image: &[u8], style: &Style) -> Result<Vec<Vec<Option<Color>>>, Box<dyn Error + 'static>> {
+    // The header is "P6", width, height and maximum value separated by whitespace, followed by a
+    // single whitespace byte before the pixels.
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while image.get(pos).is_some_and(u8::is_ascii_whitespace) {
+            pos += 1;
+        }
+        let start = pos;
+        while image.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        if start == pos {
+            return Err("Truncated PPM header".into());
+        }
+        fields.push(std::str::from_utf8(&image[start..pos])?);
+    }
+    if fields[0] != "P6" {
+        return Err(format!("Unsupported PPM format {}", fields[0]).into());
+    }
+    let (width, height): (usize, usize) = (fields[1].parse()?, fields[2].parse()?);
+    if fields[3] != "255" {
+        return Err(format!("Unsupported PPM maximum value {}", fields[3]).into());
+    }
+    let pixels = image.get(pos + 1..).ok_or("Truncated PPM header")?;
+    let bytes = width.checked_mul(height).and_then(|n| n.checked_mul(3)).ok_or("PPM image too large")?;
+    if pixels.len() < bytes {
+        return Err("Truncated PPM image".into());
+    }
+
+    let cell_size = style.cell_size.max(1);
+    (0..height / cell_size)
+        .map(|row| {
+            (0..width / cell_size)
+                .map(|col| {
+                    let offset = (row * cell_size * width + col * cell_size) * 3;
+                    let rgb = [pixels[offset], pixels[offset + 1], pixels[offset + 2]];
+                    if rgb == style.unpainted {
+                        return Ok(None);
+                    }
+                    style.palette.color_of_rgb(rgb)
+                        .map(Some)
+                        .ok_or_else(|| format!("Unknown colour {:02x}{:02x}{:02x} at column {} of row {}", rgb[0], rgb[1], rgb[2], col, row).into())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RobotDirection;
+
+    /// A white panel above an unpainted one, next to a black one.
+    fn hull() -> HullMap {
+        let mut hull = HullMap::default();
+        hull.paint(RobotPosition(0, 1), Color::WHITE);
+        hull.paint(RobotPosition(1, 0), Color::BLACK);
+        hull
+    }
+
+    #[test]
+    fn test_parse_rgb() {
+        assert_eq!(parse_rgb("#ff8000"), Ok([255, 128, 0]));
+        assert_eq!(parse_rgb("0a0B0c"), Ok([10, 11, 12]));
+        assert!(parse_rgb("fff").is_err());
+        assert!(parse_rgb("gg0000").is_err());
+        assert!(parse_rgb("+1+1+1").is_err());
+    }
+
+    #[test]
+    fn test_ppm() {
+        let style = Style { cell_size: 1, ..Style::default() };
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        expected.extend_from_slice(&[255, 255, 255, 90, 90, 90, 90, 90, 90, 0, 0, 0]);
+        assert_eq!(ppm(&hull(), &style), expected);
+        assert_eq!(ppm(&hull(), &Style::default()).len(), "P6\n20 20\n255\n".len() + 20 * 20 * 3);
+
+        let hull = hull();
+        let frame = Frame {
+            hull: &hull,
+            robot: RobotPosition(1, 1),
+            direction: RobotDirection::Up,
+            min: RobotPosition(0, 0),
+            max: RobotPosition(1, 1),
+            palette: &style.palette,
+        };
+        assert_eq!(ppm_frame(&frame, &style)[expected.len() - 12..], [255, 255, 255, 220, 40, 40, 90, 90, 90, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_svg() {
+        let style = Style { cell_size: 4, unpainted: [1, 2, 3], ..Style::default() };
+        assert_eq!(svg(&hull(), &style), r##"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="8" viewBox="0 0 8 8" shape-rendering="crispEdges">
+<rect width="8" height="8" fill="#010203"/>
+<rect x="0" y="0" width="4" height="4" fill="#ffffff"/>
+<rect x="4" y="4" width="4" height="4" fill="#000000"/>
+</svg>
+"##);
+    }
+
+    #[test]
+    fn test_load() {
+        let palette = Palette::parse("0 black . 000000\n1 white # ffffff\n2 red R ff0000").unwrap();
+        let style = Style { cell_size: 3, palette, ..Style::default() };
+        let text = "#.R\n..#\n";
+        let hull = load(text.as_bytes(), &style, (1, 1)).unwrap();
+        assert_eq!(hull.len(), 3);
+        assert_eq!(*hull.get_color(RobotPosition(1, 1)), Color(2));
+        assert_eq!(*hull.get_color(RobotPosition(1, 0)), Color::WHITE);
+        assert_eq!(hull.text(&style.palette), text);
+
+        // Images keep black panels apart from unpainted ones.
+        let mut painted = hull.clone();
+        painted.paint(RobotPosition(0, 0), Color::BLACK);
+        let image = ppm(&painted, &style);
+        let loaded = load(&image, &style, (0, 0)).unwrap();
+        assert_eq!(loaded.len(), 4);
+        assert_eq!(ppm(&loaded, &style), image);
+        assert_eq!(*loaded.get_color(RobotPosition(1, -1)), Color::BLACK);
+
+        assert!(load(b"#x\n", &style, (0, 0)).is_err());
+        assert!(load(b"P6\n3 3\n255\n", &style, (0, 0)).is_err());
+        assert!(load(b"P6\n3 3\n", &style, (0, 0)).is_err());
+        assert!(load(b"P6 1 1 255", &style, (0, 0)).is_err());
+        assert!(load(b"P6 99999999999 99999999999 255\n", &style, (0, 0)).is_err());
+        assert!(load(b"P6x 1 1 255\n\0\0\0", &Style { cell_size: 1, ..Style::default() }, (0, 0)).is_err());
+        assert!(load(b"P6\n1 1\n255\n\x01\x02\x03", &Style { cell_size: 1, ..Style::default() }, (0, 0)).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! The hull painting robot of p21 and p22: the hull, the robot's brains, and the reports and
+//! images of its painting.
+
+use std::{collections::HashMap, error::Error, fmt};
+
+use crate::{brain::RobotBrain, palette::Palette, path::Step};
+
+pub use crate::cli::run;
+
+pub mod brain;
+mod cli;
+pub mod export;
+pub mod palette;
+pub mod path;
+pub mod stats;
+
+/// Let the robot paint `hull` as `brain` tells it, starting at the origin. Returns the painted
+/// hull and every step the robot made.
+pub fn paint(brain: &mut dyn RobotBrain, hull: HullMap) -> Result<(HullMap, Vec<Step>), Box<dyn Error + 'static>> {
+    let mut current_position = RobotPosition(0, 0);
+    let mut current_direction = RobotDirection::Up;
+    let mut hull_memory = hull;
+    let mut steps = Vec::new();
+    loop {
+        let read = *hull_memory.get_color(current_position);
+        let (color, turn) = match brain.step(read)? {
+            Some(step) => step,
+            None => break,
+        };
+
+        hull_memory.paint(current_position, color);
+        steps.push(Step { position: current_position, direction: current_direction, read, painted: color, turn });
+        current_direction = current_direction.turn(turn);
+
+        current_position = current_position.move_forward(current_direction);
+    }
+    Ok((hull_memory, steps))
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct HullMap(HashMap<RobotPosition, Color>);
+
+/// Index of a colour in the `Palette`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Color(usize);
+
+impl Color {
+    pub const BLACK: Color = Color(0);
+    pub const WHITE: Color = Color(1);
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RobotDirection {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl fmt::Display for RobotDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RobotDirection::*;
+        match self {
+            Up => write!(f, "up"),
+            Right => write!(f, "right"),
+            Down => write!(f, "down"),
+            Left => write!(f, "left"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Turn {
+    Left,
+    Right,
+}
+
+impl fmt::Display for Turn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Turn::Left => write!(f, "left"),
+            Turn::Right => write!(f, "right"),
+        }
+    }
+}
+
+impl HullMap {
+    /// An unpainted hull, except for the start panel if it's white.
+    pub fn start(color: Color) -> Self {
+        let mut hull = HullMap::default();
+        if color != Color::BLACK {
+            hull.paint(RobotPosition(0, 0), color);
+        }
+        hull
+    }
+
+    pub fn get_color(&self, pos: RobotPosition) -> &Color {
+        self.0.get(&pos).unwrap_or(&Color::BLACK)
+    }
+
+    pub fn paint(&mut self, pos: RobotPosition, color: Color) {
+        self.0.insert(pos, color);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The painted area as rows from the top, `None` for panels that were never painted. Up is
+    /// towards larger y.
+    pub fn rows(&self) -> Vec<Vec<Option<Color>>> {
+        let (min, max) = bounds(self.0.keys().cloned());
+        self.rows_in(min, max)
+    }
+
+    /// Like `rows`, but for the area between the corners `min` and `max`.
+    pub fn rows_in(&self, min: RobotPosition, max: RobotPosition) -> Vec<Vec<Option<Color>>> {
+        let (xmin, ymin, xmax, ymax) = (min.0, min.1, max.0, max.1);
+        (ymin..=ymax).rev()
+            .map(|line_pos| {
+                (xmin..=xmax)
+                    .map(|col_pos| self.0.get(&RobotPosition(col_pos, line_pos)).cloned())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Rows of panels from the top, lit where they are painted white.
+    pub fn lit(&self) -> Vec<Vec<bool>> {
+        self.rows().iter()
+            .map(|row| row.iter().map(|&color| color == Some(Color::WHITE)).collect())
+            .collect()
+    }
+
+    /// One glyph of `palette` per panel, unpainted panels are drawn as black.
+    pub fn text(&self, palette: &Palette) -> String {
+        let mut text = String::new();
+        for row in self.rows() {
+            text.extend(row.into_iter().map(|color| palette.paint(color.unwrap_or(Color::BLACK)).glyph));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl std::fmt::Display for HullMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text(&Palette::default()))
+    }
+}
+
+/// The lower left and upper right corner of the area covering all `positions`.
+fn bounds<I: Iterator<Item=RobotPosition>>(positions: I) -> (RobotPosition, RobotPosition) {
+    let positions: Vec<RobotPosition> = positions.collect();
+    let xmin = positions.iter().map(|pos| pos.0).min().unwrap_or(0);
+    let xmax = positions.iter().map(|pos| pos.0).max().unwrap_or(0);
+    let ymin = positions.iter().map(|pos| pos.1).min().unwrap_or(0);
+    let ymax = positions.iter().map(|pos| pos.1).max().unwrap_or(0);
+    (RobotPosition(xmin, ymin), RobotPosition(xmax, ymax))
+}
+
+impl RobotDirection {
+    pub fn turn(self, turn: Turn) -> RobotDirection {
+        match turn {
+            Turn::Left => self.rotate_left(),
+            Turn::Right => self.rotate_right(),
+        }
+    }
+
+    pub fn rotate_left(self) -> RobotDirection {
+        use RobotDirection::*;
+        match self {
+            Up => Left,
+            Left => Down,
+            Down => Right,
+            Right => Up,
+        }
+    }
+
+    pub fn rotate_right(self) -> RobotDirection {
+        use RobotDirection::*;
+        match self {
+            Up => Right,
+            Right => Down,
+            Down => Left,
+            Left => Up,
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub struct RobotPosition(pub isize, pub isize);
+
+impl RobotPosition {
+    pub fn move_forward(self, direction: RobotDirection) -> Self {
+        use RobotDirection::*;
+        match direction {
+            Up => RobotPosition(self.0, self.1 + 1),
+            Right => RobotPosition(self.0 + 1, self.1),
+            Down => RobotPosition(self.0, self.1 - 1),
+            Left => RobotPosition(self.0 - 1, self.1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Style;
+
+    #[test]
+    fn test_lit() {
+        // "HI" as printed after painting, with the start panel left of it painted black.
+        let text = concat!(
+            " #  # ###\n",
+            " #  #  # \n",
+            " ####  # \n",
+            " #  #  # \n",
+            " #  #  # \n",
+            " #  # ###\n",
+        );
+        let mut hull = export::load(text.as_bytes(), &Style::default(), (0, 0)).unwrap();
+        hull.paint(RobotPosition(0, 0), Color::BLACK);
+        assert_eq!(hull.to_string(), text);
+        assert!(!hull.lit()[0][0]);
+        assert_eq!(ocr::read(&hull.lit()), Ok("HI".to_string()));
+    }
+}
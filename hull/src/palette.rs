@@ -0,0 +1,104 @@
+use std::error::Error;
+
+use crate::{export::{parse_rgb, Rgb}, Color};
+
+/// One colour of the palette.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Paint {
+    pub name: String,
+    /// What the painting program reads and writes for this colour.
+    pub value: isize,
+    /// Character in text pictures.
+    pub glyph: char,
+    pub rgb: Rgb,
+}
+
+/// The colours the robot can paint. `Color(i)` is the i-th entry, the first two are the hull's
+/// black and white.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Palette(Vec<Paint>);
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette(vec![
+            Paint { name: "black".to_string(), value: 0, glyph: ' ', rgb: [0, 0, 0] },
+            Paint { name: "white".to_string(), value: 1, glyph: '#', rgb: [255, 255, 255] },
+        ])
+    }
+}
+
+impl Palette {
+    /// Parse a palette with one colour per line, written as `VALUE NAME GLYPH RRGGBB`. The glyph
+    /// `space` stands for a blank. Empty lines and lines starting with `;` are skipped.
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error + 'static>> {
+        let mut paints: Vec<Paint> = Vec::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with(';')) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 4 {
+                return Err(format!("Expected 'VALUE NAME GLYPH RRGGBB' in palette, got '{}'", line).into());
+            }
+            let glyph = match fields[2] {
+                "space" => ' ',
+                glyph if glyph.chars().count() == 1 => glyph.chars().next().unwrap(),
+                glyph => return Err(format!("Glyph '{}' is not a single character", glyph).into()),
+            };
+            let paint = Paint { name: fields[1].to_string(), value: fields[0].parse()?, glyph, rgb: parse_rgb(fields[3])? };
+            if let Some(other) = paints.iter().find(|other| other.value == paint.value || other.glyph == paint.glyph) {
+                return Err(format!("Colours {} and {} share a value or glyph", other.name, paint.name).into());
+            }
+            paints.push(paint);
+        }
+        if paints.len() < 2 {
+            return Err("A palette needs at least a black and a white colour".into());
+        }
+        Ok(Palette(paints))
+    }
+
+    pub fn paint(&self, color: Color) -> &Paint {
+        &self.0[color.0]
+    }
+
+    pub fn set_rgb(&mut self, color: Color, rgb: Rgb) {
+        self.0[color.0].rgb = rgb;
+    }
+
+    pub fn color_of_value(&self, value: isize) -> Option<Color> {
+        self.0.iter().position(|paint| paint.value == value).map(Color)
+    }
+
+    pub fn color_of_glyph(&self, glyph: char) -> Option<Color> {
+        self.0.iter().position(|paint| paint.glyph == glyph).map(Color)
+    }
+
+    pub fn color_of_rgb(&self, rgb: Rgb) -> Option<Color> {
+        self.0.iter().position(|paint| paint.rgb == rgb).map(Color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let palette = Palette::parse("\
+            ; value name glyph colour
+            0 black space 000000
+            1 white # ffffff
+
+            7 red R ff0000
+        ").unwrap();
+        assert_eq!(palette.color_of_value(7), Some(Color(2)));
+        assert_eq!(palette.color_of_value(2), None);
+        assert_eq!(palette.color_of_glyph(' '), Some(Color::BLACK));
+        assert_eq!(palette.color_of_rgb([255, 0, 0]), Some(Color(2)));
+        assert_eq!(palette.paint(Color(2)).name, "red");
+        assert_eq!(palette.paint(Color::WHITE), Palette::default().paint(Color::WHITE));
+
+        assert!(Palette::parse("0 black space 000000").is_err());
+        assert!(Palette::parse("0 black space 000000\n1 white space ffffff").is_err());
+        assert!(Palette::parse("0 black space 000000\n0 white # ffffff").is_err());
+        assert!(Palette::parse("0 black space 000000\n1 white ## ffffff").is_err());
+        assert!(Palette::parse("0 black space 000000\n1 white #").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::{fmt, io};
+
+use crate::{bounds, palette::Palette, Color, HullMap, RobotDirection, RobotPosition, Turn};
+
+/// One step of the robot: it reads the panel at `position` while facing `direction`, paints it
+/// and turns. Afterwards it moves forward one panel.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Step {
+    pub position: RobotPosition,
+    pub direction: RobotDirection,
+    pub read: Color,
+    pub painted: Color,
+    pub turn: Turn,
+}
+
+impl Step {
+    /// Where the robot ends up after this step.
+    pub fn next(&self) -> (RobotPosition, RobotDirection) {
+        let direction = self.direction.turn(self.turn);
+        (self.position.move_forward(direction), direction)
+    }
+
+    /// The step in words, with the colour names of `palette`.
+    pub fn describe(&self, palette: &Palette) -> String {
+        format!(
+            "at ({}, {}) facing {} read {}, painted {} and turned {}",
+            self.position.0, self.position.1, self.direction,
+            palette.paint(self.read).name, palette.paint(self.painted).name, self.turn,
+        )
+    }
+}
+
+/// The steps as CSV with a header line, with the colour names of `palette`.
+pub fn csv(steps: &[Step], palette: &Palette) -> String {
+    let mut log = String::from("step,x,y,direction,read,painted,turn\n");
+    for (i, step) in steps.iter().enumerate() {
+        log.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            i, step.position.0, step.position.1, step.direction,
+            palette.paint(step.read).name, palette.paint(step.painted).name, step.turn,
+        ));
+    }
+    log
+}
+
+/// Index of the first step where the robot moves or paints differently in two runs, or where one
+/// run ends before the other. The colours read are ignored, they differ from the first step on
+/// between runs on different start colours.
+pub fn divergence(a: &[Step], b: &[Step]) -> Option<usize> {
+    let same = |a: &Step, b: &Step| (a.position, a.direction, a.painted, a.turn) == (b.position, b.direction, b.painted, b.turn);
+    match a.iter().zip(b).position(|(a, b)| !same(a, b)) {
+        Some(step) => Some(step),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FrameFormat {
+    Ppm,
+    Text,
+}
+
+/// The hull and the robot between two steps. All frames of an animation cover the same area.
+pub struct Frame<'a> {
+    pub hull: &'a HullMap,
+    pub robot: RobotPosition,
+    pub direction: RobotDirection,
+    pub min: RobotPosition,
+    pub max: RobotPosition,
+    pub palette: &'a Palette,
+}
+
+impl<'a> Frame<'a> {
+    pub fn rows(&self) -> Vec<Vec<Option<Color>>> {
+        self.hull.rows_in(self.min, self.max)
+    }
+
+    /// Row and column of the robot in `rows`.
+    pub fn robot_cell(&self) -> (usize, usize) {
+        ((self.max.1 - self.robot.1) as usize, (self.robot.0 - self.min.0) as usize)
+    }
+}
+
+/// Text frame like `HullMap::text`, with the robot drawn as an arrow.
+impl<'a> fmt::Display for Frame<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let robot = self.robot_cell();
+        for (y, row) in self.rows().into_iter().enumerate() {
+            for (x, color) in row.into_iter().enumerate() {
+                let c = match (color.unwrap_or(Color::BLACK), (y, x) == robot) {
+                    (_, true) => match self.direction {
+                        RobotDirection::Up => '^',
+                        RobotDirection::Right => '>',
+                        RobotDirection::Down => 'v',
+                        RobotDirection::Left => '<',
+                    },
+                    (color, false) => self.palette.paint(color).glyph,
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replay `steps` on the `initial` hull, calling `frame` with the frame number and the frame
+/// before the first step and after every `every`-th step. The final frame is always shown.
+pub fn animate<F>(steps: &[Step], initial: &HullMap, palette: &Palette, every: usize, mut frame: F) -> io::Result<()>
+where
+    F: FnMut(usize, &Frame) -> io::Result<()>,
+{
+    let origin = RobotPosition(0, 0);
+    let robot = steps.iter().map(|step| step.next().0);
+    let (min, max) = bounds(Some(origin).into_iter().chain(initial.0.keys().cloned()).chain(robot));
+
+    let mut hull = initial.clone();
+    let mut frames = 0;
+    frame(frames, &Frame { hull: &hull, robot: origin, direction: RobotDirection::Up, min, max, palette })?;
+    for (i, step) in steps.iter().enumerate() {
+        hull.paint(step.position, step.painted);
+        if (i + 1) % every.max(1) == 0 || i + 1 == steps.len() {
+            let (robot, direction) = step.next();
+            frames += 1;
+            frame(frames, &Frame { hull: &hull, robot, direction, min, max, palette })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Paints a white square clockwise, then turns back into it and paints the first panel black.
+    fn square() -> Vec<Step> {
+        let mut steps = Vec::new();
+        let (mut position, mut direction) = (RobotPosition(0, 0), RobotDirection::Up);
+        let moves = [
+            (Color::WHITE, Turn::Right),
+            (Color::WHITE, Turn::Right),
+            (Color::WHITE, Turn::Right),
+            (Color::WHITE, Turn::Right),
+            (Color::BLACK, Turn::Left),
+        ];
+        for (i, &(painted, turn)) in moves.iter().enumerate() {
+            let read = if i == 4 { Color::WHITE } else { Color::BLACK };
+            let step = Step { position, direction, read, painted, turn };
+            steps.push(step);
+            let next = step.next();
+            position = next.0;
+            direction = next.1;
+        }
+        steps
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(csv(&square()[..2], &Palette::default()), "\
+step,x,y,direction,read,painted,turn
+0,0,0,up,black,white,right
+1,1,0,right,black,white,right
+");
+        assert_eq!(square()[4].describe(&Palette::default()), "at (0, 0) facing up read white, painted black and turned left");
+    }
+
+    #[test]
+    fn test_divergence() {
+        let steps = square();
+        assert_eq!(divergence(&steps, &steps), None);
+        assert_eq!(divergence(&steps, &steps[..3]), Some(3));
+
+        let mut other = steps.clone();
+        other[0].read = Color::WHITE;
+        assert_eq!(divergence(&steps, &other), None);
+        other[2].painted = Color::BLACK;
+        assert_eq!(divergence(&steps, &other), Some(2));
+    }
+
+    #[test]
+    fn test_animate() {
+        let mut frames = Vec::new();
+        animate(&square(), &HullMap::default(), &Palette::default(), 2, |number, frame| {
+            frames.push((number, frame.to_string()));
+            Ok(())
+        }).unwrap();
+
+        let frames: Vec<(usize, &str)> = frames.iter().map(|(number, text)| (*number, text.as_str())).collect();
+        assert_eq!(frames, vec![
+            (0, " ^ \n   \n"),
+            (1, " ##\n  v\n"),
+            (2, " ^#\n ##\n"),
+            (3, "< #\n ##\n"),
+        ]);
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{bounds, path::Step, HullMap, RobotPosition};
+
+/// Number of panels listed as most visited.
+const MOST_VISITED: usize = 5;
+
+/// What the robot did to the hull.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Stats {
+    /// Panels moved, one per step.
+    pub path_length: usize,
+    /// How often each panel was painted. The robot paints every panel it stops on.
+    pub paint_counts: HashMap<RobotPosition, usize>,
+    /// Panels painted more than once.
+    pub repainted: usize,
+    /// Paint operations that changed the colour of their panel.
+    pub colour_changes: usize,
+    /// Panels that ended up in a different colour than they started with.
+    pub net_changes: usize,
+    /// Lower left and upper right corner of the area covered by the path.
+    pub min: RobotPosition,
+    pub max: RobotPosition,
+    /// The most often painted panels, most visited first and by position on ties.
+    pub most_visited: Vec<(RobotPosition, usize)>,
+}
+
+impl Stats {
+    /// Statistics of the robot taking `steps` on the `initial` hull.
+    pub fn new(steps: &[Step], initial: &HullMap) -> Self {
+        let mut paint_counts = HashMap::new();
+        let mut hull = initial.clone();
+        let mut colour_changes = 0;
+        for step in steps {
+            *paint_counts.entry(step.position).or_insert(0) += 1;
+            if *hull.get_color(step.position) != step.painted {
+                colour_changes += 1;
+            }
+            hull.paint(step.position, step.painted);
+        }
+
+        let net_changes = paint_counts.keys()
+            .filter(|&&position| hull.get_color(position) != initial.get_color(position))
+            .count();
+        let origin = RobotPosition(0, 0);
+        let (min, max) = bounds(Some(origin).into_iter().chain(steps.iter().map(|step| step.next().0)));
+
+        let mut most_visited: Vec<(RobotPosition, usize)> = paint_counts.iter().map(|(&position, &count)| (position, count)).collect();
+        most_visited.sort_by_key(|&(position, count)| (std::cmp::Reverse(count), position.0, position.1));
+        most_visited.truncate(MOST_VISITED);
+
+        Stats {
+            path_length: steps.len(),
+            repainted: paint_counts.values().filter(|&&count| count > 1).count(),
+            paint_counts,
+            colour_changes,
+            net_changes,
+            min,
+            max,
+            most_visited,
+        }
+    }
+
+    /// Panels painted at least once.
+    pub fn panels_painted(&self) -> usize {
+        self.paint_counts.len()
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max.0 - self.min.0 + 1) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max.1 - self.min.1 + 1) as usize
+    }
+
+    /// The statistics as a JSON object, without the counts of every panel.
+    pub fn json(&self) -> String {
+        let most_visited: Vec<String> = self.most_visited.iter()
+            .map(|(position, count)| format!(r#"{{"x": {}, "y": {}, "count": {}}}"#, position.0, position.1, count))
+            .collect();
+        format!(
+            concat!(
+                "{{\"path_length\": {}, \"panels_painted\": {}, \"repainted\": {}, ",
+                "\"colour_changes\": {}, \"net_changes\": {}, ",
+                "\"bounding_box\": {{\"min\": [{}, {}], \"max\": [{}, {}], \"width\": {}, \"height\": {}}}, ",
+                "\"most_visited\": [{}]}}",
+            ),
+            self.path_length, self.panels_painted(), self.repainted,
+            self.colour_changes, self.net_changes,
+            self.min.0, self.min.1, self.max.0, self.max.1, self.width(), self.height(),
+            most_visited.join(", "),
+        )
+    }
+}
+
+/// The statistics as a table.
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Path length               {:>8}", self.path_length)?;
+        writeln!(f, "Panels painted            {:>8}", self.panels_painted())?;
+        writeln!(f, "Panels painted repeatedly {:>8}", self.repainted)?;
+        writeln!(f, "Colour changes            {:>8}", self.colour_changes)?;
+        writeln!(f, "Net colour changes        {:>8}", self.net_changes)?;
+        writeln!(
+            f,
+            "Bounding box              ({}, {}) to ({}, {}), {}x{}",
+            self.min.0, self.min.1, self.max.0, self.max.1, self.width(), self.height(),
+        )?;
+        writeln!(f, "Most visited panels")?;
+        for (position, count) in &self.most_visited {
+            writeln!(f, "  {:<23} {:>8}", format!("({}, {})", position.0, position.1), count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{brain::LangtonsAnt, paint, Color};
+
+    #[test]
+    fn test_stats() {
+        // Paints a 2x2 square white, then the start panel black again.
+        let (_, steps) = paint(&mut LangtonsAnt::new(5), HullMap::default()).unwrap();
+        let stats = Stats::new(&steps, &HullMap::default());
+        assert_eq!(stats.path_length, 5);
+        assert_eq!(stats.panels_painted(), 4);
+        assert_eq!(stats.paint_counts[&RobotPosition(0, 0)], 2);
+        assert_eq!(stats.repainted, 1);
+        assert_eq!(stats.colour_changes, 5);
+        assert_eq!(stats.net_changes, 3);
+        assert_eq!((stats.min, stats.max), (RobotPosition(-1, -1), RobotPosition(1, 0)));
+        assert_eq!(stats.most_visited[..2], [(RobotPosition(0, 0), 2), (RobotPosition(0, -1), 1)]);
+        assert_eq!(stats.most_visited.len(), 4);
+
+        assert_eq!(stats.to_string(), "\
+Path length                      5
+Panels painted                   4
+Panels painted repeatedly        1
+Colour changes                   5
+Net colour changes               3
+Bounding box              (-1, -1) to (1, 0), 3x2
+Most visited panels
+  (0, 0)                         2
+  (0, -1)                        1
+  (1, -1)                        1
+  (1, 0)                         1
+");
+        assert_eq!(stats.json(), concat!(
+            r#"{"path_length": 5, "panels_painted": 4, "repainted": 1, "colour_changes": 5, "net_changes": 3, "#,
+            r#""bounding_box": {"min": [-1, -1], "max": [1, 0], "width": 3, "height": 2}, "#,
+            r#""most_visited": [{"x": 0, "y": 0, "count": 2}, {"x": 0, "y": -1, "count": 1}, "#,
+            r#"{"x": 1, "y": -1, "count": 1}, {"x": 1, "y": 0, "count": 1}]}"#,
+        ));
+
+        // On a white start panel the ant paints it black.
+        let initial = HullMap::start(Color::WHITE);
+        let (_, steps) = paint(&mut LangtonsAnt::new(1), initial.clone()).unwrap();
+        let stats = Stats::new(&steps, &initial);
+        assert_eq!((stats.colour_changes, stats.net_changes), (1, 1));
+        let stats = Stats::new(&[], &initial);
+        assert_eq!((stats.path_length, stats.panels_painted(), stats.width(), stats.height()), (0, 0, 1, 1));
+    }
+}
@@ -0,0 +1,170 @@
+//! Static recovery of basic blocks from an Intcode image.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::intcode::{Operation, ParameterMode};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// Address after the last instruction.
+    pub end: usize,
+    pub instructions: Vec<(usize, Operation)>,
+    /// Statically known successors, in order: fallthrough first, then the jump target.
+    pub successors: Vec<usize>,
+    /// The block ends with a jump to an address only known at runtime.
+    pub indirect: bool,
+    /// The block ends because the next address does not decode to a valid instruction.
+    pub invalid_end: bool,
+}
+
+/// How execution can leave an instruction.
+pub enum Flow {
+    Next,
+    Halt,
+    Jump { target: Option<usize>, conditional: bool },
+}
+
+pub fn flow(op: &Operation) -> Flow {
+    let (cond, dest, jump_if) = match op {
+        Operation::Halt => return Flow::Halt,
+        Operation::JumpIfTrue { bool_param, jump_dest } => (bool_param, jump_dest, true),
+        Operation::JumpIfFalse { bool_param, jump_dest } => (bool_param, jump_dest, false),
+        _ => return Flow::Next,
+    };
+    let target = match dest {
+        (ParameterMode::Immediate, addr) if *addr >= 0 => Some(*addr as usize),
+        _ => None,
+    };
+    match cond {
+        (ParameterMode::Immediate, value) if (*value != 0) != jump_if => Flow::Next,
+        (ParameterMode::Immediate, _) => Flow::Jump { target, conditional: false },
+        _ => Flow::Jump { target, conditional: true },
+    }
+}
+
+/// Split all code reachable from `entry` into basic blocks.
+///
+/// Code reachable only through indirect jumps can't be found statically. Because such jumps
+/// usually return behind a call, the address after an unconditional jump is explored as well if it
+/// decodes to a valid instruction. Blocks found this way may actually be data, but they are only
+/// ever used if execution reaches them.
+pub fn find_blocks(memory: &[isize], entry: usize) -> BTreeMap<usize, BasicBlock> {
+    let mut leaders = BTreeSet::new();
+    let mut instructions = BTreeMap::new();
+    let mut todo = vec![entry];
+    leaders.insert(entry);
+
+    while let Some(mut ip) = todo.pop() {
+        while ip < memory.len() && !instructions.contains_key(&ip) {
+            let op = match Operation::try_decode(&memory[ip..]) {
+                Ok(op) => op,
+                Err(_) => break,
+            };
+            instructions.insert(ip, op);
+            let next = ip + op.size();
+            match flow(&op) {
+                Flow::Next => ip = next,
+                Flow::Halt => {
+                    leaders.insert(next);
+                    todo.push(next);
+                    break;
+                }
+                Flow::Jump { target, conditional } => {
+                    if let Some(target) = target {
+                        leaders.insert(target);
+                        todo.push(target);
+                    }
+                    leaders.insert(next);
+                    if conditional {
+                        ip = next;
+                    } else {
+                        todo.push(next);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &start in leaders.iter().filter(|start| instructions.contains_key(start)) {
+        let mut block = BasicBlock {
+            start,
+            end: start,
+            instructions: Vec::new(),
+            successors: Vec::new(),
+            indirect: false,
+            invalid_end: false,
+        };
+        let mut ip = start;
+        loop {
+            let op = match instructions.get(&ip) {
+                Some(op) => *op,
+                None => {
+                    block.invalid_end = true;
+                    break;
+                }
+            };
+            block.instructions.push((ip, op));
+            let next = ip + op.size();
+            block.end = next;
+            match flow(&op) {
+                Flow::Next if leaders.contains(&next) => {
+                    block.successors.push(next);
+                    break;
+                }
+                Flow::Next => ip = next,
+                Flow::Halt => break,
+                Flow::Jump { target, conditional } => {
+                    if conditional {
+                        block.successors.push(next);
+                    }
+                    match target {
+                        Some(target) => block.successors.push(target),
+                        None => block.indirect = true,
+                    }
+                    break;
+                }
+            }
+        }
+        blocks.insert(start, block);
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_blocks() {
+        let prog = [
+            3, 20,           // 0: input
+            1005, 20, 10,    // 2: if [20] goto 10
+            104, 0,          // 5: output 0
+            1105, 1, 13,     // 7: goto 13
+            104, 1,          // 10: output 1
+            99,              // 12: halt
+            2106, 0, 21,     // 13: goto [rb + 21]
+            99,              // 16: only reachable by an indirect jump
+        ];
+        let blocks = find_blocks(&prog, 0);
+        let starts: Vec<usize> = blocks.keys().cloned().collect();
+        assert_eq!(starts, vec![0, 5, 10, 13, 16]);
+        assert_eq!(blocks[&0].successors, vec![5, 10]);
+        assert_eq!(blocks[&0].end, 5);
+        assert_eq!(blocks[&5].successors, vec![13]);
+        assert!(blocks[&10].successors.is_empty());
+        assert!(blocks[&13].indirect);
+        assert!(!blocks[&16].indirect);
+    }
+
+    #[test]
+    fn test_invalid_end() {
+        let blocks = find_blocks(&[1101, 1, 1, 7, 42], 0);
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[&0].invalid_end);
+        assert_eq!(blocks[&0].instructions.len(), 1);
+    }
+}
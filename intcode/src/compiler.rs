@@ -0,0 +1,618 @@
+//! Compiler for a small structured language to Intcode.
+//!
+//! ```text
+//! // Globals need a constant initial value.
+//! let limit = 10;
+//!
+//! fn fib(n) {
+//!     if n < 2 {
+//!         return n;
+//!     }
+//!     return fib(n - 1) + fib(n - 2);
+//! }
+//!
+//! fn main() {
+//!     let i = input();
+//!     while i < limit {
+//!         output(fib(i));
+//!         i = i + 1;
+//!     }
+//! }
+//! ```
+//!
+//! All values are integers, conditions treat every non-zero value as true. Expressions support
+//! `+ - *`, the comparisons `< <= > >= == !=`, `!`, unary `-` and the short circuiting `&&` and
+//! `||`. There is no division, Intcode has no instruction for it. `input()` reads the next input
+//! value and `output(x)` writes `x`. Execution starts at `main`, which takes no parameters, and
+//! the program halts when `main` returns.
+//!
+//! The relative base is used as frame pointer, frames are placed on a stack behind the program.
+//! A frame of a function with `n` parameters is laid out as
+//!
+//! | relative address | content                                 |
+//! |------------------|-----------------------------------------|
+//! | 0                | return address                          |
+//! | 1 ..= n          | parameters                              |
+//! | n + 1 ..         | local variables and temporary values    |
+//!
+//! To call a function, the caller writes the return address and arguments directly behind its own
+//! frame, moves the relative base there and jumps to the function. The function stores its return
+//! value in a fixed memory cell and jumps back, the caller then moves the relative base back.
+
+use std::collections::HashMap;
+
+use crate::Error;
+
+use self::parser::{BinaryOp, Expr, Function, Stmt, StmtKind};
+
+mod lexer;
+mod parser;
+
+/// Compile `source` to an Intcode program that can be run with `Program::new`.
+pub fn compile(source: &str) -> Result<Vec<isize>, Error> {
+    let module = parser::parse(lexer::tokenize(source)?)?;
+    let mut gen = Codegen::default();
+    let ret = gen.new_label();
+    let stack = gen.new_label();
+
+    for global in &module.globals {
+        let label = gen.new_label();
+        if gen.globals.insert(global.name.clone(), label).is_some() {
+            return Err(format!("line {}: global '{}' is defined twice", global.line, global.name).into());
+        }
+    }
+    gen.ret = ret;
+
+    for function in &module.functions {
+        if BUILTINS.contains(&function.name.as_str()) {
+            return Err(format!("line {}: '{}' is a builtin function", function.line, function.name).into());
+        }
+        let label = gen.new_label();
+        if gen.functions.insert(function.name.clone(), (label, function.params.len())).is_some() {
+            return Err(format!("line {}: function '{}' is defined twice", function.line, function.name).into());
+        }
+    }
+    let main = match gen.functions.get("main") {
+        Some(&(label, 0)) => label,
+        Some(_) => return Err("'main' must not take parameters".into()),
+        None => return Err("missing function 'main'".into()),
+    };
+
+    // Entry: set up the stack, call main and halt once it returns.
+    let after_main = gen.new_label();
+    gen.emit(9, &[Operand::imm_label(stack)]);
+    gen.copy(Operand::imm_label(after_main), Operand::rel(0));
+    gen.jump(main);
+    gen.place(after_main);
+    gen.emit(99, &[]);
+
+    for function in &module.functions {
+        gen.function(function)?;
+    }
+
+    gen.place(ret);
+    gen.code.push(Cell::Value(0));
+    for global in &module.globals {
+        gen.place(gen.globals[&global.name]);
+        gen.code.push(Cell::Value(global.value));
+    }
+    gen.place(stack);
+
+    gen.code.iter().map(|cell| match cell {
+        Cell::Value(v) => Ok(*v),
+        Cell::Label(l) => gen.labels[*l].map(|addr| addr as isize).ok_or_else(|| "internal error: unplaced label".into()),
+        Cell::Frame(_) | Cell::NegFrame => Err("internal error: unpatched frame size".into()),
+    }).collect()
+}
+
+const BUILTINS: &[&str] = &["input", "output"];
+
+#[derive(Debug, Copy, Clone)]
+enum Cell {
+    Value(isize),
+    /// Address of a label.
+    Label(usize),
+    /// Frame size of the current function plus an offset.
+    Frame(isize),
+    /// Negative frame size of the current function.
+    NegFrame,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Operand {
+    mode: isize,
+    cell: Cell,
+}
+
+impl Operand {
+    fn imm(value: isize) -> Self {
+        Operand { mode: 1, cell: Cell::Value(value) }
+    }
+
+    fn imm_label(label: usize) -> Self {
+        Operand { mode: 1, cell: Cell::Label(label) }
+    }
+
+    fn abs_label(label: usize) -> Self {
+        Operand { mode: 0, cell: Cell::Label(label) }
+    }
+
+    fn rel(slot: isize) -> Self {
+        Operand { mode: 2, cell: Cell::Value(slot) }
+    }
+
+    fn constant(&self) -> Option<isize> {
+        match (self.mode, self.cell) {
+            (1, Cell::Value(v)) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Codegen {
+    code: Vec<Cell>,
+    labels: Vec<Option<usize>>,
+    functions: HashMap<String, (usize, usize)>,
+    globals: HashMap<String, usize>,
+    ret: usize,
+
+    scopes: Vec<HashMap<String, isize>>,
+    next_slot: isize,
+    max_slot: isize,
+    /// Continue and break label of all loops around the current statement.
+    loops: Vec<(usize, usize)>,
+}
+
+impl Codegen {
+    fn new_label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    fn emit(&mut self, opcode: isize, operands: &[Operand]) {
+        let modes: isize = operands.iter()
+            .zip([100, 1000, 10_000].iter())
+            .map(|(op, factor)| op.mode * factor)
+            .sum();
+        self.code.push(Cell::Value(opcode + modes));
+        self.code.extend(operands.iter().map(|op| op.cell));
+    }
+
+    fn copy(&mut self, src: Operand, dest: Operand) {
+        self.emit(1, &[src, Operand::imm(0), dest]);
+    }
+
+    fn jump(&mut self, label: usize) {
+        self.emit(5, &[Operand::imm(1), Operand::imm_label(label)]);
+    }
+
+    fn jump_if(&mut self, cond: Operand, label: usize) {
+        self.emit(5, &[cond, Operand::imm_label(label)]);
+    }
+
+    fn jump_unless(&mut self, cond: Operand, label: usize) {
+        self.emit(6, &[cond, Operand::imm_label(label)]);
+    }
+
+    fn emit_return(&mut self, value: Operand) {
+        self.copy(value, Operand::abs_label(self.ret));
+        self.emit(6, &[Operand::imm(0), Operand::rel(0)]);
+    }
+
+    fn alloc_slot(&mut self) -> isize {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        self.max_slot = self.max_slot.max(self.next_slot);
+        slot
+    }
+
+    fn temp(&mut self) -> Operand {
+        Operand::rel(self.alloc_slot())
+    }
+
+    fn lookup(&self, name: &str, line: usize) -> Result<Operand, Error> {
+        if let Some(slot) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            return Ok(Operand::rel(*slot));
+        }
+        match self.globals.get(name) {
+            Some(label) => Ok(Operand::abs_label(*label)),
+            None => Err(format!("line {}: unknown variable '{}'", line, name).into()),
+        }
+    }
+
+    fn function(&mut self, function: &Function) -> Result<(), Error> {
+        let start = self.code.len();
+        let label = self.functions[&function.name].0;
+        self.place(label);
+
+        let mut params = HashMap::new();
+        for (idx, param) in function.params.iter().enumerate() {
+            if params.insert(param.clone(), idx as isize + 1).is_some() {
+                return Err(format!("line {}: parameter '{}' is defined twice", function.line, param).into());
+            }
+        }
+        self.scopes = vec![params];
+        self.next_slot = function.params.len() as isize + 1;
+        self.max_slot = self.next_slot;
+        self.loops.clear();
+
+        self.block(&function.body)?;
+        self.emit_return(Operand::imm(0));
+
+        let frame_size = self.max_slot;
+        for cell in &mut self.code[start..] {
+            match *cell {
+                Cell::Frame(offset) => *cell = Cell::Value(frame_size + offset),
+                Cell::NegFrame => *cell = Cell::Value(-frame_size),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<(), Error> {
+        let saved = self.next_slot;
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+        self.scopes.pop();
+        self.next_slot = saved;
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        let line = stmt.line;
+        let saved = self.next_slot;
+        match &stmt.kind {
+            StmtKind::Let(name, value) => {
+                let value = self.expr(value, line)?;
+                self.next_slot = saved;
+                let slot = self.alloc_slot();
+                self.copy(value, Operand::rel(slot));
+                self.scopes.last_mut().expect("inside a block").insert(name.clone(), slot);
+                return Ok(());
+            }
+            StmtKind::Assign(name, value) => {
+                let value = self.expr(value, line)?;
+                let dest = self.lookup(name, line)?;
+                self.copy(value, dest);
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                let cond = self.expr(cond, line)?;
+                self.next_slot = saved;
+                let else_label = self.new_label();
+                self.jump_unless(cond, else_label);
+                self.block(then)?;
+                if otherwise.is_empty() {
+                    self.place(else_label);
+                } else {
+                    let end_label = self.new_label();
+                    self.jump(end_label);
+                    self.place(else_label);
+                    self.block(otherwise)?;
+                    self.place(end_label);
+                }
+            }
+            StmtKind::While(cond, body) => {
+                let start_label = self.new_label();
+                let end_label = self.new_label();
+                self.place(start_label);
+                let cond = self.expr(cond, line)?;
+                self.next_slot = saved;
+                self.jump_unless(cond, end_label);
+                self.loops.push((start_label, end_label));
+                self.block(body)?;
+                self.loops.pop();
+                self.jump(start_label);
+                self.place(end_label);
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.expr(value, line)?,
+                    None => Operand::imm(0),
+                };
+                self.emit_return(value);
+            }
+            StmtKind::Break | StmtKind::Continue => {
+                let &(continue_label, break_label) = self.loops.last()
+                    .ok_or_else(|| format!("line {}: break or continue outside of a loop", line))?;
+                let target = if let StmtKind::Break = stmt.kind { break_label } else { continue_label };
+                self.jump(target);
+            }
+            StmtKind::Block(stmts) => self.block(stmts)?,
+            StmtKind::Expr(e) => {
+                self.expr(e, line)?;
+            }
+        }
+        self.next_slot = saved;
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr, line: usize) -> Result<Operand, Error> {
+        match expr {
+            Expr::Number(n) => Ok(Operand::imm(*n)),
+            Expr::Var(name) => self.lookup(name, line),
+            Expr::Call(name, args) => self.call(name, args, line),
+            Expr::Neg(inner) => {
+                let value = self.expr(inner, line)?;
+                let result = self.temp();
+                self.emit(2, &[value, Operand::imm(-1), result]);
+                Ok(result)
+            }
+            Expr::Not(inner) => {
+                let value = self.expr(inner, line)?;
+                let result = self.temp();
+                self.emit(8, &[value, Operand::imm(0), result]);
+                Ok(result)
+            }
+            Expr::Binary(op @ BinaryOp::And, left, right) | Expr::Binary(op @ BinaryOp::Or, left, right) => {
+                let is_and = *op == BinaryOp::And;
+                let result = self.temp();
+                let end_label = self.new_label();
+                self.copy(Operand::imm(if is_and { 0 } else { 1 }), result);
+                let left = self.expr(left, line)?;
+                if is_and {
+                    self.jump_unless(left, end_label);
+                } else {
+                    self.jump_if(left, end_label);
+                }
+                let right = self.expr(right, line)?;
+                self.emit(8, &[right, Operand::imm(0), result]);
+                self.emit(8, &[result, Operand::imm(0), result]);
+                self.place(end_label);
+                Ok(result)
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.expr(left, line)?;
+                let right = self.expr(right, line)?;
+                if let (Some(l), Some(r)) = (left.constant(), right.constant()) {
+                    return Ok(Operand::imm(fold(*op, l, r)));
+                }
+                let result = self.temp();
+                match op {
+                    BinaryOp::Add => self.emit(1, &[left, right, result]),
+                    BinaryOp::Sub => {
+                        self.emit(2, &[right, Operand::imm(-1), result]);
+                        self.emit(1, &[left, result, result]);
+                    }
+                    BinaryOp::Mul => self.emit(2, &[left, right, result]),
+                    BinaryOp::Less => self.emit(7, &[left, right, result]),
+                    BinaryOp::Greater => self.emit(7, &[right, left, result]),
+                    BinaryOp::LessEqual => {
+                        self.emit(7, &[right, left, result]);
+                        self.emit(8, &[result, Operand::imm(0), result]);
+                    }
+                    BinaryOp::GreaterEqual => {
+                        self.emit(7, &[left, right, result]);
+                        self.emit(8, &[result, Operand::imm(0), result]);
+                    }
+                    BinaryOp::Equal => self.emit(8, &[left, right, result]),
+                    BinaryOp::NotEqual => {
+                        self.emit(8, &[left, right, result]);
+                        self.emit(8, &[result, Operand::imm(0), result]);
+                    }
+                    BinaryOp::And | BinaryOp::Or => unreachable!("handled above"),
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], line: usize) -> Result<Operand, Error> {
+        let arity_error = |expected: usize| format!("line {}: '{}' takes {} arguments, got {}", line, name, expected, args.len());
+        match name {
+            "input" => {
+                if !args.is_empty() {
+                    return Err(arity_error(0).into());
+                }
+                let result = self.temp();
+                self.emit(3, &[result]);
+                return Ok(result);
+            }
+            "output" => {
+                if args.len() != 1 {
+                    return Err(arity_error(1).into());
+                }
+                let value = self.expr(&args[0], line)?;
+                self.emit(4, &[value]);
+                return Ok(Operand::imm(0));
+            }
+            _ => (),
+        }
+
+        let (label, arity) = *self.functions.get(name)
+            .ok_or_else(|| format!("line {}: unknown function '{}'", line, name))?;
+        if arity != args.len() {
+            return Err(arity_error(arity).into());
+        }
+
+        // Evaluate all arguments first, a nested call would overwrite the next frame.
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.expr(arg, line)?);
+        }
+        for (idx, value) in values.into_iter().enumerate() {
+            self.copy(value, Operand { mode: 2, cell: Cell::Frame(idx as isize + 1) });
+        }
+        let return_label = self.new_label();
+        self.copy(Operand::imm_label(return_label), Operand { mode: 2, cell: Cell::Frame(0) });
+        self.emit(9, &[Operand { mode: 1, cell: Cell::Frame(0) }]);
+        self.jump(label);
+        self.place(return_label);
+        self.emit(9, &[Operand { mode: 1, cell: Cell::NegFrame }]);
+
+        let result = self.temp();
+        self.copy(Operand::abs_label(self.ret), result);
+        Ok(result)
+    }
+}
+
+fn fold(op: BinaryOp, l: isize, r: isize) -> isize {
+    use BinaryOp::*;
+    let truth = |b: bool| if b { 1 } else { 0 };
+    match op {
+        Add => l.wrapping_add(r),
+        Sub => l.wrapping_sub(r),
+        Mul => l.wrapping_mul(r),
+        Less => truth(l < r),
+        LessEqual => truth(l <= r),
+        Greater => truth(l > r),
+        GreaterEqual => truth(l >= r),
+        Equal => truth(l == r),
+        NotEqual => truth(l != r),
+        And => truth(l != 0 && r != 0),
+        Or => truth(l != 0 || r != 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Program, ProgramState};
+
+    fn run(source: &str, inputs: &[isize]) -> Vec<isize> {
+        let mut prog = Program::new(compile(source).unwrap());
+        let mut inputs = inputs.iter();
+        let mut input = None;
+        let mut outputs = Vec::new();
+        loop {
+            let (state, out) = prog.run(&mut input);
+            outputs.extend(out);
+            match state {
+                ProgramState::Halt => return outputs,
+                ProgramState::AwaitInput => input = Some(*inputs.next().expect("program wants more input")),
+                ProgramState::StepLimit | ProgramState::Breakpoint | ProgramState::InfiniteLoop | ProgramState::Trap | ProgramState::Crash => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let source = "
+            fn main() {
+                let a = input();
+                let b = input();
+                output(a + b);
+                output(a - b);
+                output(a * b);
+                output(-a);
+                output(2 + 3 * 4 - 1);
+                output(a < b);
+                output(a <= b);
+                output(a > b);
+                output(a >= b);
+                output(a == b);
+                output(a != b);
+                output(!a);
+            }";
+        assert_eq!(run(source, &[7, 3]), vec![10, 4, 21, -7, 13, 0, 0, 1, 1, 0, 1, 0]);
+        assert_eq!(run(source, &[3, 3]), vec![6, 0, 9, -3, 13, 0, 1, 0, 1, 1, 0, 0]);
+
+        // Constants are folded with wrapping arithmetic.
+        let source = "
+            fn main() {
+                output(9223372036854775807 + 1);
+                output(-9223372036854775807 - 2);
+                output(4611686018427387904 * 2);
+            }";
+        assert_eq!(run(source, &[]), vec![isize::MIN, isize::MAX, isize::MIN]);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = "
+            let calls = 0;
+
+            fn check(x) {
+                calls = calls + 1;
+                return x;
+            }
+
+            fn main() {
+                let i = 0;
+                while 1 {
+                    i = i + 1;
+                    if i == 3 {
+                        continue;
+                    } else if i > 5 {
+                        break;
+                    }
+                    output(i);
+                }
+                output(check(0) && check(1));
+                output(check(1) || check(0));
+                output(check(2) && check(3));
+                output(calls);
+            }";
+        assert_eq!(run(source, &[]), vec![1, 2, 4, 5, 0, 1, 1, 4]);
+    }
+
+    #[test]
+    fn test_recursion() {
+        let source = "
+            let limit = 10;
+
+            fn fib(n) {
+                if n < 2 {
+                    return n;
+                }
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            fn sum3(a, b, c) {
+                let x = a;
+                {
+                    let x = b;
+                    a = x + c;
+                }
+                return a + x;
+            }
+
+            fn main() {
+                let i = input();
+                while i < limit {
+                    output(fib(i));
+                    i = i + 1;
+                }
+                output(sum3(1, fib(5), sum3(1, 1, 1)));
+            }";
+        assert_eq!(run(source, &[5]), vec![5, 8, 13, 21, 34, 9]);
+    }
+
+    #[test]
+    fn test_hull_painting_brain() {
+        // Paint every panel in the opposite colour and alternate turns.
+        let source = "
+            fn main() {
+                let turn = 0;
+                while 1 {
+                    output(1 - input());
+                    output(turn);
+                    turn = !turn;
+                }
+            }";
+        let mut prog = Program::new(compile(source).unwrap());
+        assert_eq!(prog.run(&mut Some(0)).1, vec![1, 0]);
+        assert_eq!(prog.run(&mut Some(1)).1, vec![0, 1]);
+        assert_eq!(prog.run(&mut Some(1)).1, vec![0, 0]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(compile("fn foo() {}").is_err());
+        assert!(compile("fn main(x) {}").is_err());
+        assert!(compile("fn main() { x = 1; }").is_err());
+        assert!(compile("fn main() { foo(); }").is_err());
+        assert!(compile("fn f(a) {} fn main() { f(); }").is_err());
+        assert!(compile("fn main() { break; }").is_err());
+        assert!(compile("fn input() {} fn main() {}").is_err());
+        assert!(compile("fn main() {} fn main() {}").is_err());
+        assert!(compile("let a = 1; let a = 2; fn main() {}").is_err());
+    }
+}
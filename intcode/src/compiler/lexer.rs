@@ -0,0 +1,130 @@
+use crate::Error;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Number(isize),
+    Fn,
+    Let,
+    If,
+    Else,
+    While,
+    Return,
+    Break,
+    Continue,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    Semicolon,
+    Assign,
+    Plus,
+    Minus,
+    Star,
+    Not,
+    And,
+    Or,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+/// Split `source` into tokens, each tagged with its line number.
+pub fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, Error> {
+    let mut tokens = Vec::new();
+    for (idx, line) in source.lines().enumerate() {
+        let line_nr = idx + 1;
+        let line = match line.find("//") {
+            Some(pos) => &line[..pos],
+            None => line,
+        };
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            use Token::*;
+            let token = match c {
+                c if c.is_whitespace() => continue,
+                '(' => LParen,
+                ')' => RParen,
+                '{' => LBrace,
+                '}' => RBrace,
+                ',' => Comma,
+                ';' => Semicolon,
+                '+' => Plus,
+                '-' => Minus,
+                '*' => Star,
+                '=' | '!' | '<' | '>' if chars.peek() == Some(&'=') => {
+                    chars.next();
+                    match c {
+                        '=' => Equal,
+                        '!' => NotEqual,
+                        '<' => LessEqual,
+                        _ => GreaterEqual,
+                    }
+                }
+                '=' => Assign,
+                '!' => Not,
+                '<' => Less,
+                '>' => Greater,
+                '&' | '|' if chars.peek() == Some(&c) => {
+                    chars.next();
+                    if c == '&' { And } else { Or }
+                }
+                c if c.is_ascii_digit() => {
+                    let mut num = c.to_string();
+                    while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit() || **d == '_') {
+                        num.push(d);
+                        chars.next();
+                    }
+                    let num = num.replace('_', "");
+                    Number(num.parse().map_err(|e| format!("line {}: invalid number {}: {}", line_nr, num, e))?)
+                }
+                c if c.is_alphabetic() || c == '_' => {
+                    let mut ident = c.to_string();
+                    while let Some(&d) = chars.peek().filter(|d| d.is_alphanumeric() || **d == '_') {
+                        ident.push(d);
+                        chars.next();
+                    }
+                    match ident.as_str() {
+                        "fn" => Fn,
+                        "let" => Let,
+                        "if" => If,
+                        "else" => Else,
+                        "while" => While,
+                        "return" => Return,
+                        "break" => Break,
+                        "continue" => Continue,
+                        _ => Ident(ident),
+                    }
+                }
+                c => return Err(format!("line {}: unexpected character '{}'", line_nr, c).into()),
+            };
+            tokens.push((token, line_nr));
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Token::*;
+
+    #[test]
+    fn test_tokenize() {
+        let tokens: Vec<Token> = tokenize("let x_1 = 1_000 <= y; // comment\n!a != -b && c || d")
+            .unwrap()
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect();
+        assert_eq!(tokens, vec![
+            Let, Ident("x_1".to_string()), Assign, Number(1000), LessEqual, Ident("y".to_string()), Semicolon,
+            Not, Ident("a".to_string()), NotEqual, Minus, Ident("b".to_string()), And, Ident("c".to_string()), Or, Ident("d".to_string()),
+        ]);
+        assert!(tokenize("a & b").is_err());
+        assert!(tokenize("a / b").is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use crate::{
+    Error,
+    compiler::lexer::Token,
+};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Expr {
+    Number(isize),
+    Var(String),
+    Call(String, Vec<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StmtKind {
+    Let(String, Expr),
+    Assign(String, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Break,
+    Continue,
+    Block(Vec<Stmt>),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Stmt {
+    pub line: usize,
+    pub kind: StmtKind,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Function {
+    pub line: usize,
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Global {
+    pub line: usize,
+    pub name: String,
+    pub value: isize,
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Module {
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+pub fn parse(tokens: Vec<(Token, usize)>) -> Result<Module, Error> {
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut module = Module::default();
+    while let Some(token) = parser.peek() {
+        match token {
+            Token::Fn => module.functions.push(parser.function()?),
+            Token::Let => module.globals.push(parser.global()?),
+            _ => return Err(parser.error("expected 'fn' or 'let'")),
+        }
+    }
+    Ok(module)
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        match self.peek() {
+            Some(token) => format!("line {}: {}, found {:?}", self.line(), msg, token).into(),
+            None => format!("line {}: {}, found end of input", self.line(), msg).into(),
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), Error> {
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {:?}", token)))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("expected identifier")),
+        }
+    }
+
+    fn global(&mut self) -> Result<Global, Error> {
+        let line = self.line();
+        self.expect(Token::Let)?;
+        let name = self.ident()?;
+        self.expect(Token::Assign)?;
+        let value = match self.expr()? {
+            Expr::Number(n) => n,
+            _ => return Err(format!("line {}: global '{}' needs a constant value", line, name).into()),
+        };
+        self.expect(Token::Semicolon)?;
+        Ok(Global { line, name, value })
+    }
+
+    fn function(&mut self) -> Result<Function, Error> {
+        let line = self.line();
+        self.expect(Token::Fn)?;
+        let name = self.ident()?;
+        self.expect(Token::LParen)?;
+        let mut params = Vec::new();
+        if !self.eat(&Token::RParen) {
+            loop {
+                params.push(self.ident()?);
+                if self.eat(&Token::RParen) {
+                    break;
+                }
+                self.expect(Token::Comma)?;
+            }
+        }
+        let body = self.block()?;
+        Ok(Function { line, name, params, body })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, Error> {
+        self.expect(Token::LBrace)?;
+        let mut stmts = Vec::new();
+        while !self.eat(&Token::RBrace) {
+            if self.peek().is_none() {
+                return Err(self.error("expected '}'"));
+            }
+            stmts.push(self.stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, Error> {
+        let line = self.line();
+        let kind = match self.peek() {
+            Some(Token::Let) => {
+                self.pos += 1;
+                let name = self.ident()?;
+                self.expect(Token::Assign)?;
+                let value = self.expr()?;
+                self.expect(Token::Semicolon)?;
+                StmtKind::Let(name, value)
+            }
+            Some(Token::If) => {
+                self.pos += 1;
+                let cond = self.expr()?;
+                let then = self.block()?;
+                let otherwise = if !self.eat(&Token::Else) {
+                    Vec::new()
+                } else if self.peek() == Some(&Token::If) {
+                    vec![self.stmt()?]
+                } else {
+                    self.block()?
+                };
+                StmtKind::If(cond, then, otherwise)
+            }
+            Some(Token::While) => {
+                self.pos += 1;
+                let cond = self.expr()?;
+                StmtKind::While(cond, self.block()?)
+            }
+            Some(Token::Return) => {
+                self.pos += 1;
+                let value = if self.peek() == Some(&Token::Semicolon) { None } else { Some(self.expr()?) };
+                self.expect(Token::Semicolon)?;
+                StmtKind::Return(value)
+            }
+            Some(Token::LBrace) => StmtKind::Block(self.block()?),
+            Some(Token::Break) => {
+                self.pos += 1;
+                self.expect(Token::Semicolon)?;
+                StmtKind::Break
+            }
+            Some(Token::Continue) => {
+                self.pos += 1;
+                self.expect(Token::Semicolon)?;
+                StmtKind::Continue
+            }
+            Some(Token::Ident(_)) if self.tokens.get(self.pos + 1).map(|(t, _)| t) == Some(&Token::Assign) => {
+                let name = self.ident()?;
+                self.pos += 1;
+                let value = self.expr()?;
+                self.expect(Token::Semicolon)?;
+                StmtKind::Assign(name, value)
+            }
+            _ => {
+                let expr = self.expr()?;
+                self.expect(Token::Semicolon)?;
+                StmtKind::Expr(expr)
+            }
+        };
+        Ok(Stmt { line, kind })
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        self.binary(0)
+    }
+
+    /// Parse binary operators by precedence climbing, `level` indexes `LEVELS`.
+    fn binary(&mut self, level: usize) -> Result<Expr, Error> {
+        const LEVELS: &[&[(Token, BinaryOp)]] = &[
+            &[(Token::Or, BinaryOp::Or)],
+            &[(Token::And, BinaryOp::And)],
+            &[
+                (Token::Equal, BinaryOp::Equal),
+                (Token::NotEqual, BinaryOp::NotEqual),
+                (Token::Less, BinaryOp::Less),
+                (Token::LessEqual, BinaryOp::LessEqual),
+                (Token::Greater, BinaryOp::Greater),
+                (Token::GreaterEqual, BinaryOp::GreaterEqual),
+            ],
+            &[(Token::Plus, BinaryOp::Add), (Token::Minus, BinaryOp::Sub)],
+            &[(Token::Star, BinaryOp::Mul)],
+        ];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        'outer: loop {
+            for (token, op) in LEVELS[level] {
+                if self.eat(token) {
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(*op, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        if self.eat(&Token::Minus) {
+            return Ok(match self.unary()? {
+                Expr::Number(n) => Expr::Number(-n),
+                e => Expr::Neg(Box::new(e)),
+            });
+        }
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        match self.peek() {
+            Some(Token::Number(n)) => {
+                let n = *n;
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let e = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(e)
+            }
+            Some(Token::Ident(_)) => {
+                let name = self.ident()?;
+                if !self.eat(&Token::LParen) {
+                    return Ok(Expr::Var(name));
+                }
+                let mut args = Vec::new();
+                if !self.eat(&Token::RParen) {
+                    loop {
+                        args.push(self.expr()?);
+                        if self.eat(&Token::RParen) {
+                            break;
+                        }
+                        self.expect(Token::Comma)?;
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            _ => Err(self.error("expected expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::lexer::tokenize;
+
+    fn parse_expr(s: &str) -> Expr {
+        let source = format!("fn main() {{ {}; }}", s);
+        let module = parse(tokenize(&source).unwrap()).unwrap();
+        match &module.functions[0].body[0].kind {
+            StmtKind::Expr(e) => e.clone(),
+            x => panic!("not an expression: {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        use Expr::*;
+        let var = |s: &str| Box::new(Var(s.to_string()));
+        assert_eq!(parse_expr("a + b * c"), Binary(BinaryOp::Add, var("a"), Box::new(Binary(BinaryOp::Mul, var("b"), var("c")))));
+        assert_eq!(parse_expr("a - b - c"), Binary(BinaryOp::Sub, Box::new(Binary(BinaryOp::Sub, var("a"), var("b"))), var("c")));
+        assert_eq!(parse_expr("a < b || !c"), Binary(BinaryOp::Or, Box::new(Binary(BinaryOp::Less, var("a"), var("b"))), Box::new(Not(var("c")))));
+        assert_eq!(parse_expr("-(f(1, -2))"), Neg(Box::new(Call("f".to_string(), vec![Number(1), Number(-2)]))));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse(tokenize("fn main() { let = 1; }").unwrap()).is_err());
+        assert!(parse(tokenize("fn main() { 1 + ; }").unwrap()).is_err());
+        assert!(parse(tokenize("fn main() { ").unwrap()).is_err());
+        assert!(parse(tokenize("let x = y;").unwrap()).is_err());
+        assert!(parse(tokenize("x = 1;").unwrap()).is_err());
+    }
+}
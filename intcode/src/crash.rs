@@ -0,0 +1,156 @@
+//! Reports on Intcode programs that can't continue.
+
+use std::fmt;
+
+use crate::intcode::DecodeError;
+
+/// Why a program crashed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Fault {
+    InvalidInstruction(DecodeError),
+    /// The instruction pointer ran past the end of memory.
+    OutsideMemory,
+    /// A parameter the instruction writes to is in immediate mode.
+    WriteInImmediateMode,
+    /// A parameter points to a negative address or past the end of memory.
+    AddressOutOfRange(isize),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::InvalidInstruction(err) => write!(f, "{}", err),
+            Fault::OutsideMemory => write!(f, "Instruction pointer outside of memory"),
+            Fault::WriteInImmediateMode => write!(f, "Write to a parameter in immediate mode"),
+            Fault::AddressOutOfRange(addr) => write!(f, "Address {} outside of memory", addr),
+        }
+    }
+}
+
+/// State of a crashed program, created by `Program::crash_report`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CrashReport {
+    pub fault: Fault,
+    pub instruction_ptr: usize,
+    pub relative_base: isize,
+    pub steps: usize,
+    /// The last executed instructions, oldest first.
+    pub history: Vec<(usize, String)>,
+    /// All inputs consumed since the program was created.
+    pub inputs: Vec<isize>,
+    /// Cells that differ from the original image: address, original and current value.
+    pub changes: Vec<(usize, isize, isize)>,
+    /// Disassembly around the faulting instruction.
+    pub disassembly: Vec<(usize, String)>,
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Crash at {} after {} instructions: {}", self.instruction_ptr, self.steps, self.fault)?;
+        writeln!(f, "Registers: ip {}, rb {}", self.instruction_ptr, self.relative_base)?;
+
+        writeln!(f, "\nLast {} executed instructions:", self.history.len())?;
+        for (addr, text) in &self.history {
+            writeln!(f, "  {:>8}  {}", addr, text)?;
+        }
+
+        let inputs: Vec<String> = self.inputs.iter().map(|value| value.to_string()).collect();
+        writeln!(f, "\nConsumed {} inputs{}{}", inputs.len(), if inputs.is_empty() { "" } else { ": " }, inputs.join(","))?;
+
+        writeln!(f, "\nMemory changed in {} cells:", self.changes.len())?;
+        for (addr, old, new) in &self.changes {
+            writeln!(f, "  {:>8}  {} -> {}", addr, old, new)?;
+        }
+
+        writeln!(f, "\nDisassembly:")?;
+        for (addr, text) in &self.disassembly {
+            let marker = if *addr == self.instruction_ptr { '>' } else { ' ' };
+            writeln!(f, "{} {:>8}  {}", marker, addr, text)?;
+        }
+        if self.instruction_ptr >= self.disassembly.last().map_or(0, |(addr, _)| addr + 1) {
+            writeln!(f, "> {:>8}  {}", self.instruction_ptr, self.fault)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Program, ProgramState};
+
+    #[test]
+    fn test_crash_report() {
+        let code = vec![
+            3, 12,           // 0: in [12]
+            1001, 12, 1, 12, // 2: add [12], 1, [12]
+            1005, 12, 10,    // 6: jnz [12], 10
+            99,              // 9: halt
+            42, 0,           // 10: unknown instruction
+            0,
+        ];
+        let mut prog = Program::new(code);
+        assert_eq!(prog.run(&mut Some(41)), (ProgramState::Crash, vec![]));
+        let report = prog.crash_report().unwrap();
+        assert_eq!(report.to_string(), "\
+Crash at 10 after 3 instructions: Unknown instruction 42
+Registers: ip 10, rb 0
+
+Last 3 executed instructions:
+         0  in [12]
+         2  add [12], 1, [12]
+         6  jnz [12], 10
+
+Consumed 1 inputs: 41
+
+Memory changed in 1 cells:
+        12  0 -> 42
+
+Disassembly:
+         0  in [12]
+         2  add [12], 1, [12]
+         6  jnz [12], 10
+         9  halt
+>       10  data 42
+        11  data 0
+        12  data 42
+        13  data 0
+");
+
+        // Overwrites the following output with a truncated input instruction.
+        let mut prog = Program::with_memory_size(vec![1101, 1, 2, 4, 104], 5);
+        prog.set_crash_history(1);
+        assert_eq!(prog.run(&mut None), (ProgramState::Crash, vec![]));
+        let report = prog.crash_report().unwrap();
+        assert_eq!(report.fault, Fault::InvalidInstruction(DecodeError::Truncated));
+        assert_eq!(report.history, vec![(0, "add 1, 2, [4]".to_string())]);
+        assert_eq!(report.changes, vec![(4, 104, 3)]);
+        assert_eq!(report.disassembly, vec![(0, "add 1, 2, [4]".to_string()), (4, "data 3".to_string())]);
+
+        let mut prog = Program::with_memory_size(vec![1105, 1, 100], 3);
+        assert_eq!(prog.run(&mut None), (ProgramState::Crash, vec![]));
+        assert!(prog.crash_report().unwrap().to_string().ends_with(">      100  Instruction pointer outside of memory\n"));
+        prog.set_instruction_ptr(0);
+        prog.set_step_limit(Some(1));
+        assert_eq!(prog.run(&mut None).0, ProgramState::StepLimit);
+        assert!(prog.crash_report().is_none());
+    }
+
+    #[test]
+    fn test_memory_faults() {
+        let mut prog = Program::new(vec![11101, 1, 1, 0, 99]);
+        assert_eq!(prog.run(&mut None), (ProgramState::Crash, vec![]));
+        let report = prog.crash_report().unwrap();
+        assert_eq!((report.fault, report.instruction_ptr), (Fault::WriteInImmediateMode, 0));
+        assert_eq!(report.changes, vec![]);
+
+        let mut prog = Program::new(vec![1, 99999999, 0, 0, 99]);
+        assert_eq!(prog.run(&mut None), (ProgramState::Crash, vec![]));
+        assert!(prog.crash_report().unwrap().to_string().starts_with("Crash at 0 after 0 instructions: Address 99999999 outside of memory\n"));
+
+        let mut prog = Program::new(vec![109, -5, 1201, 0, 0, 0, 99]);
+        assert_eq!(prog.run(&mut None), (ProgramState::Crash, vec![]));
+        let report = prog.crash_report().unwrap();
+        assert_eq!((report.fault, report.instruction_ptr, report.relative_base), (Fault::AddressOutOfRange(-5), 2, -5));
+    }
+}
@@ -0,0 +1,727 @@
+//! Debug adapter protocol server, lets editors debug Intcode programs.
+//!
+//! The server speaks the protocol over a pair of byte streams, usually stdin and stdout. Every
+//! message is a JSON object preceded by a `Content-Length` header. The program has no source
+//! code, so its disassembly is offered as source instead: one line per instruction or data cell,
+//! breakpoints are set on these lines. The variables view shows the registers and the memory.
+//!
+//! The `launch` request takes these arguments:
+//!
+//! | argument      | content                                                           |
+//! |---------------|-------------------------------------------------------------------|
+//! | `program`     | path of the program, as text or binary image                      |
+//! | `input`       | input values as array of numbers, or as text in ASCII mode        |
+//! | `ascii`       | exchange input and output as ASCII text                           |
+//! | `memory`      | total number of memory cells                                      |
+//! | `stopOnEntry` | stop before the first instruction                                 |
+//!
+//! When the program waits for more input, it stops. Enter `input VALUES` in the debug console
+//! to provide more values, other expressions like `ip`, `rb`, `[12]` or `[rb-3]` are evaluated.
+
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::{
+    Error,
+    blocks::find_blocks,
+    image::Image,
+    intcode::{Operation, Program, ProgramState},
+    json::{self, Value},
+};
+
+pub const THREAD_ID: i64 = 1;
+/// The disassembly is the only source.
+pub const SOURCE_REFERENCE: i64 = 1;
+const REGISTERS: i64 = 1;
+const MEMORY: i64 = 2;
+/// Memory cells returned by a `variables` request without `count`.
+const MEMORY_PAGE: usize = 1000;
+/// Instructions executed between checks for a `pause` request.
+const SLICE: usize = 10_000;
+
+/// Messages read from the client, errors are passed as text between threads.
+type Incoming = Receiver<Result<Value, String>>;
+
+/// Read the next message, `None` at the end of the stream.
+pub fn read_message<R: BufRead>(reader: &mut R) -> Result<Option<Value>, Error> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return match length {
+                None => Ok(None),
+                Some(_) => Err("Unexpected end of stream in message header".into()),
+            };
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let length = length.ok_or("Message header without Content-Length")?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    json::parse(std::str::from_utf8(&body)?).map(Some)
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> Result<(), Error> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Serve a single debug session until the client disconnects or closes `input`.
+pub fn serve<R: Read + Send + 'static, W: Write>(input: R, output: W) -> Result<(), Error> {
+    let (sender, receiver) = mpsc::channel();
+    // Requests are read on their own thread, so a running program can be paused.
+    thread::spawn(move || {
+        let mut reader = BufReader::new(input);
+        loop {
+            let message = match read_message(&mut reader) {
+                Ok(Some(message)) => Ok(message),
+                Ok(None) => break,
+                Err(e) => Err(e.to_string()),
+            };
+            let failed = message.is_err();
+            if sender.send(message).is_err() || failed {
+                break;
+            }
+        }
+    });
+    Debugger::new(output).serve(&receiver)
+}
+
+/// Disassembly of a program image with one line per instruction. Cells that aren't reachable
+/// code are listed one per line as data.
+#[derive(Debug)]
+pub struct Listing {
+    lines: Vec<(usize, String)>,
+}
+
+impl Listing {
+    pub fn new(memory: &[isize], entry: usize) -> Self {
+        let code: BTreeMap<usize, Operation> = find_blocks(memory, entry)
+            .values()
+            .flat_map(|block| block.instructions.iter().cloned())
+            .collect();
+        let mut lines = Vec::new();
+        let mut addr = 0;
+        while addr < memory.len() {
+            match code.get(&addr) {
+                Some(op) => {
+                    lines.push((addr, op.to_string()));
+                    addr += op.size();
+                }
+                None => {
+                    lines.push((addr, format!("data {}", memory[addr])));
+                    addr += 1;
+                }
+            }
+        }
+        Listing { lines }
+    }
+
+    /// One based line that contains `addr`.
+    pub fn line_of(&self, addr: usize) -> Option<usize> {
+        let end = self.lines.last().map_or(0, |(start, _)| start + 4);
+        if addr >= end {
+            return None;
+        }
+        match self.lines.partition_point(|(start, _)| *start <= addr) {
+            0 => None,
+            line => Some(line),
+        }
+    }
+
+    /// Address at the start of the one based `line`.
+    pub fn addr_of(&self, line: usize) -> Option<usize> {
+        line.checked_sub(1).and_then(|idx| self.lines.get(idx)).map(|(addr, _)| *addr)
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.iter().map(|(addr, text)| format!("{:>6}  {}\n", addr, text)).collect()
+    }
+}
+
+/// The launched program.
+struct Target {
+    program: Program,
+    name: String,
+    listing: Listing,
+    inputs: VecDeque<isize>,
+    ascii: bool,
+    stop_on_entry: bool,
+    halted: bool,
+}
+
+impl Target {
+    /// Parse input values, text is split into characters in ASCII mode and into comma or space
+    /// separated numbers otherwise.
+    fn parse_inputs(&self, text: &str) -> Result<Vec<isize>, Error> {
+        if self.ascii {
+            return Ok(text.chars().chain(Some('\n')).map(|c| c as isize).collect());
+        }
+        let values: Result<Vec<isize>, _> = text.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+            .map(str::parse)
+            .collect();
+        Ok(values?)
+    }
+
+    /// The current instruction, decoded from memory as it is now.
+    fn instruction(&self) -> String {
+        let ip = self.program.instruction_ptr();
+        let cells: Vec<isize> = (ip..ip + 4).map_while(|addr| self.program.peek(addr)).collect();
+        match Operation::try_decode(&cells) {
+            Ok(op) => op.to_string(),
+            Err(_) => format!("data {}", cells.first().copied().unwrap_or_default()),
+        }
+    }
+
+    /// Evaluate an expression of the debug console: a register or a memory cell.
+    fn evaluate(&self, expression: &str) -> Result<isize, Error> {
+        let expression = expression.trim();
+        match expression {
+            "ip" => return Ok(self.program.instruction_ptr() as isize),
+            "rb" => return Ok(self.program.relative_base()),
+            "steps" => return Ok(self.program.steps() as isize),
+            _ => (),
+        }
+        let cell = expression.strip_prefix('[').and_then(|e| e.strip_suffix(']')).unwrap_or(expression).replace(' ', "");
+        let addr = if let Some(offset) = cell.strip_prefix("rb") {
+            let offset: isize = offset.strip_prefix('+').unwrap_or(offset).parse()?;
+            self.program.relative_base().checked_add(offset).ok_or("Address is out of range")?
+        } else {
+            cell.parse::<isize>()?
+        };
+        let value = if addr >= 0 { self.program.peek(addr as usize) } else { None };
+        value.ok_or_else(|| format!("Address {} is outside of memory", addr).into())
+    }
+}
+
+/// Why execution stopped.
+enum Stop {
+    Entry,
+    Step,
+    Breakpoint,
+    Pause,
+    Exception(String),
+    Exited,
+}
+
+struct Debugger<W: Write> {
+    out: W,
+    seq: i64,
+    target: Option<Target>,
+    /// Requests that arrived while the program was running.
+    pending: VecDeque<Value>,
+}
+
+impl<W: Write> Debugger<W> {
+    fn new(out: W) -> Self {
+        Debugger { out, seq: 0, target: None, pending: VecDeque::new() }
+    }
+
+    fn serve(mut self, incoming: &Incoming) -> Result<(), Error> {
+        loop {
+            let request = match self.pending.pop_front() {
+                Some(request) => request,
+                None => match incoming.recv() {
+                    Ok(message) => message?,
+                    Err(_) => return Ok(()),
+                },
+            };
+            if request.get("type").as_str() == Some("request") && !self.handle(&request, incoming)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn send(&mut self, mut entries: Vec<(&str, Value)>) -> Result<(), Error> {
+        self.seq += 1;
+        entries.push(("seq", self.seq.into()));
+        write_message(&mut self.out, &Value::object(entries))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Result<(), Error> {
+        self.send(vec![("type", "event".into()), ("event", event.into()), ("body", body)])
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, Error>) -> Result<(), Error> {
+        let mut entries = vec![
+            ("type", "response".into()),
+            ("request_seq", request.get("seq").clone()),
+            ("command", request.get("command").clone()),
+            ("success", result.is_ok().into()),
+        ];
+        match result {
+            Ok(body) => entries.push(("body", body)),
+            Err(e) => entries.push(("message", e.to_string().into())),
+        }
+        self.send(entries)
+    }
+
+    fn target(&self) -> Result<&Target, Error> {
+        self.target.as_ref().ok_or_else(|| "No program launched".into())
+    }
+
+    /// Handle a single request, returns false once the session ends.
+    fn handle(&mut self, request: &Value, incoming: &Incoming) -> Result<bool, Error> {
+        let command = request.get("command").as_str().unwrap_or_default();
+        let args = request.get("arguments");
+        let result = match command {
+            "initialize" => Ok(Value::object(vec![
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsEvaluateForHovers", true.into()),
+                ("supportsTerminateRequest", true.into()),
+            ])),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setExceptionBreakpoints" | "pause" | "disconnect" | "terminate" => Ok(Value::object::<&str>(Vec::new())),
+            "configurationDone" | "next" | "stepIn" | "stepOut" => self.target().map(|_| Value::Null),
+            "continue" => self.target().map(|_| Value::object(vec![("allThreadsContinued", true.into())])),
+            "threads" => Ok(Value::object(vec![
+                ("threads", vec![Value::object(vec![("id", THREAD_ID.into()), ("name", "intcode".into())])].into()),
+            ])),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => self.scopes(),
+            "variables" => self.variables(args),
+            "source" => self.target().map(|target| Value::object(vec![("content", target.listing.text().into())])),
+            "evaluate" => self.evaluate(args),
+            _ => Err(format!("Unsupported request '{}'", command).into()),
+        };
+        let success = result.is_ok();
+        self.respond(request, result)?;
+        if !success {
+            return Ok(true);
+        }
+
+        match command {
+            "launch" => self.event("initialized", Value::Null)?,
+            "configurationDone" if self.target()?.stop_on_entry => self.stop(Stop::Entry)?,
+            "configurationDone" | "continue" => self.resume(false, incoming)?,
+            "next" | "stepIn" | "stepOut" => self.resume(true, incoming)?,
+            "disconnect" | "terminate" => return Ok(false),
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, Error> {
+        let path = args.get("program").as_str().ok_or("Launch request is missing the program")?;
+        let image = Image::load(&fs::read(path)?)?;
+        let listing = Listing::new(&image.memory, image.entry_point);
+        let mut program = match args.get("memory").as_i64() {
+            Some(size) if size < 0 => return Err(format!("Memory size {} is negative", size).into()),
+            Some(size) => Program::with_memory_size(image.memory, size as usize),
+            None => Program::new(image.memory),
+        };
+        program.set_instruction_ptr(image.entry_point);
+
+        let mut target = Target {
+            program,
+            name: path.rsplit('/').next().unwrap_or(path).to_string(),
+            listing,
+            inputs: VecDeque::new(),
+            ascii: args.get("ascii").as_bool().unwrap_or(false),
+            stop_on_entry: args.get("stopOnEntry").as_bool().unwrap_or(false),
+            halted: false,
+        };
+        match args.get("input") {
+            Value::Null => (),
+            Value::String(text) => target.inputs = target.parse_inputs(text)?.into(),
+            Value::Array(values) => {
+                for value in values {
+                    target.inputs.push_back(value.as_i64().ok_or("Input values must be numbers")? as isize);
+                }
+            }
+            _ => return Err("Input must be an array of numbers or a string".into()),
+        }
+        self.target = Some(target);
+        Ok(Value::Null)
+    }
+
+    fn source(&self) -> Result<Value, Error> {
+        let name = format!("{} (disassembly)", self.target()?.name);
+        Ok(Value::object(vec![("name", name.into()), ("sourceReference", SOURCE_REFERENCE.into())]))
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, Error> {
+        let source = self.source()?;
+        let target = self.target.as_mut().ok_or("No program launched")?;
+        let mut addrs = HashSet::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in args.get("breakpoints").as_array().unwrap_or_default() {
+            let line = breakpoint.get("line").as_i64().unwrap_or_default();
+            let addr = if line > 0 { target.listing.addr_of(line as usize) } else { None };
+            let mut entries = vec![("verified", addr.is_some().into()), ("line", line.into())];
+            if let Some(addr) = addr {
+                addrs.insert(addr);
+                entries.push(("source", source.clone()));
+                entries.push(("instructionReference", addr.to_string().into()));
+            } else {
+                entries.push(("message", "No instruction on this line".into()));
+            }
+            breakpoints.push(Value::object(entries));
+        }
+        target.program.set_breakpoints(addrs);
+        Ok(Value::object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    fn stack_trace(&self) -> Result<Value, Error> {
+        let target = self.target()?;
+        let ip = target.program.instruction_ptr();
+        let mut frame = vec![
+            ("id", 1.into()),
+            ("name", format!("{}: {}", ip, target.instruction()).into()),
+            ("column", 1.into()),
+            ("instructionPointerReference", ip.to_string().into()),
+        ];
+        match target.listing.line_of(ip) {
+            Some(line) => {
+                frame.push(("line", line.into()));
+                frame.push(("source", self.source()?));
+            }
+            None => frame.push(("line", 0.into())),
+        }
+        Ok(Value::object(vec![("stackFrames", vec![Value::object(frame)].into()), ("totalFrames", 1.into())]))
+    }
+
+    fn scopes(&self) -> Result<Value, Error> {
+        let size = self.target()?.program.memory_size();
+        Ok(Value::object(vec![("scopes", vec![
+            Value::object(vec![("name", "Registers".into()), ("variablesReference", REGISTERS.into()), ("expensive", false.into())]),
+            Value::object(vec![
+                ("name", "Memory".into()),
+                ("variablesReference", MEMORY.into()),
+                ("indexedVariables", size.into()),
+                ("expensive", true.into()),
+            ]),
+        ].into())]))
+    }
+
+    fn variables(&self, args: &Value) -> Result<Value, Error> {
+        let target = self.target()?;
+        let variable = |name: String, value: String| {
+            Value::object(vec![("name", name.into()), ("value", value.into()), ("variablesReference", 0.into())])
+        };
+        let variables = match args.get("variablesReference").as_i64() {
+            Some(REGISTERS) => vec![
+                variable("ip".to_string(), target.program.instruction_ptr().to_string()),
+                variable("rb".to_string(), target.program.relative_base().to_string()),
+                variable("steps".to_string(), target.program.steps().to_string()),
+                variable("instruction".to_string(), target.instruction()),
+                variable("pending input".to_string(), target.inputs.len().to_string()),
+            ],
+            Some(MEMORY) => {
+                let start = args.get("start").as_i64().unwrap_or(0).max(0) as usize;
+                let count = args.get("count").as_i64().map_or(MEMORY_PAGE, |count| count.max(0) as usize);
+                (start..start.saturating_add(count))
+                    .map_while(|addr| target.program.peek(addr).map(|value| variable(format!("[{}]", addr), value.to_string())))
+                    .collect()
+            }
+            _ => return Err("Unknown variables reference".into()),
+        };
+        Ok(Value::object(vec![("variables", variables.into())]))
+    }
+
+    fn evaluate(&mut self, args: &Value) -> Result<Value, Error> {
+        let expression = args.get("expression").as_str().unwrap_or_default().trim();
+        let target = self.target.as_mut().ok_or("No program launched")?;
+        let result = if let Some(text) = expression.strip_prefix("input ") {
+            let values = target.parse_inputs(text.trim())?;
+            target.inputs.extend(&values);
+            format!("{} input values pending", target.inputs.len())
+        } else {
+            target.evaluate(expression)?.to_string()
+        };
+        Ok(Value::object(vec![("result", result.into()), ("variablesReference", 0.into())]))
+    }
+
+    fn output(&mut self, values: Vec<isize>, ascii: bool) -> Result<(), Error> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        let text: String = values.iter()
+            .map(|&value| match value {
+                0..=127 if ascii => (value as u8 as char).to_string(),
+                _ => format!("{}\n", value),
+            })
+            .collect();
+        self.event("output", Value::object(vec![("category", "stdout".into()), ("output", text.into())]))
+    }
+
+    fn stop(&mut self, stop: Stop) -> Result<(), Error> {
+        let (reason, text) = match stop {
+            Stop::Entry => ("entry", None),
+            Stop::Step => ("step", None),
+            Stop::Breakpoint => ("breakpoint", None),
+            Stop::Pause => ("pause", None),
+            Stop::Exception(text) => ("exception", Some(text)),
+            Stop::Exited => {
+                self.event("exited", Value::object(vec![("exitCode", 0.into())]))?;
+                return self.event("terminated", Value::Null);
+            }
+        };
+        let mut body = vec![("reason", reason.into()), ("threadId", THREAD_ID.into()), ("allThreadsStopped", true.into())];
+        if let Some(text) = text {
+            body.push(("description", text.clone().into()));
+            body.push(("text", text.into()));
+        }
+        self.event("stopped", Value::object(body))
+    }
+
+    /// Run a single instruction if `step` is set, otherwise run until a breakpoint is reached.
+    fn resume(&mut self, step: bool, incoming: &Incoming) -> Result<(), Error> {
+        let mut target = match self.target.take() {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        let stop = self.run(&mut target, step, incoming);
+        self.target = Some(target);
+        self.stop(stop?)
+    }
+
+    fn run(&mut self, target: &mut Target, step: bool, incoming: &Incoming) -> Result<Stop, Error> {
+        if target.halted {
+            return Ok(Stop::Exited);
+        }
+        let mut outputs = Vec::new();
+        let stop = loop {
+            let mut input = target.inputs.pop_front();
+            let consumed = input.is_some();
+            let limit = target.program.steps() + if step { 1 } else { SLICE };
+            target.program.set_step_limit(Some(limit));
+            let (state, output) = target.program.run(&mut input);
+            let consumed = consumed && input.is_none();
+            if let Some(value) = input {
+                target.inputs.push_front(value);
+            }
+            outputs.extend(output);
+            match state {
+                ProgramState::StepLimit if step => break Stop::Step,
+                ProgramState::StepLimit => (),
+                ProgramState::Breakpoint => break Stop::Breakpoint,
+                ProgramState::Halt => {
+                    target.halted = true;
+                    break Stop::Exited;
+                }
+                // The program wants the next input value, if there is one.
+                ProgramState::AwaitInput if consumed => continue,
+                ProgramState::AwaitInput => {
+                    break Stop::Exception("Waiting for input, enter 'input VALUES' in the debug console".to_string());
+                }
+                ProgramState::InfiniteLoop => break Stop::Exception("Infinite loop".to_string()),
+                ProgramState::Trap => break Stop::Exception("Trap".to_string()),
+                ProgramState::Crash => {
+                    let report = target.program.crash_report().expect("crashed program has a report");
+                    self.event("output", Value::object(vec![("category", "stderr".into()), ("output", report.to_string().into())]))?;
+                    break Stop::Exception(report.fault.to_string());
+                }
+            }
+
+            self.output(std::mem::take(&mut outputs), target.ascii)?;
+            let mut paused = None;
+            while let Ok(message) = incoming.try_recv() {
+                let message = message?;
+                if paused.is_none() && message.get("command").as_str() == Some("pause") {
+                    paused = Some(message);
+                } else {
+                    self.pending.push_back(message);
+                }
+            }
+            if let Some(request) = paused {
+                self.respond(&request, Ok(Value::Null))?;
+                break Stop::Pause;
+            }
+        };
+        self.output(outputs, target.ascii)?;
+        Ok(stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_listing() {
+        let prog = [
+            3, 11,            // 0: in [11]
+            1001, 11, 5, 11,  // 2: add [11], 5, [11]
+            204, -1,          // 6: out [rb-1]
+            99,               // 8: halt
+            0, 0, 0,
+        ];
+        let listing = Listing::new(&prog, 0);
+        assert_eq!(listing.text().lines().take(5).collect::<Vec<_>>(), vec![
+            "     0  in [11]",
+            "     2  add [11], 5, [11]",
+            "     6  out [rb-1]",
+            "     8  halt",
+            "     9  data 0",
+        ]);
+        assert_eq!(listing.line_of(4), Some(2));
+        assert_eq!(listing.line_of(11), Some(7));
+        assert_eq!(listing.line_of(100), None);
+        assert_eq!(listing.addr_of(3), Some(6));
+        assert_eq!(listing.addr_of(0), None);
+    }
+
+    #[test]
+    fn test_session() {
+        let path = std::env::temp_dir().join(format!("intcode-dap-{}.txt", std::process::id()));
+        fs::write(&path, "3,11,1001,11,5,11,4,11,99,0,0,0").unwrap();
+        let requests = vec![
+            ("initialize", Value::object(vec![("adapterID", "intcode".into())])),
+            ("launch", Value::object(vec![
+                ("program", path.to_str().unwrap().into()),
+                ("input", vec![Value::Int(37)].into()),
+                ("stopOnEntry", true.into()),
+            ])),
+            ("setBreakpoints", Value::object(vec![
+                ("source", Value::object(vec![("sourceReference", SOURCE_REFERENCE.into())])),
+                ("breakpoints", vec![Value::object(vec![("line", 3.into())]), Value::object(vec![("line", 99.into())])].into()),
+            ])),
+            ("configurationDone", Value::Null),
+            ("continue", Value::object(vec![("threadId", THREAD_ID.into())])),
+            ("stackTrace", Value::object(vec![("threadId", THREAD_ID.into())])),
+            ("variables", Value::object(vec![("variablesReference", MEMORY.into()), ("start", 11.into()), ("count", 1.into())])),
+            ("evaluate", Value::object(vec![("expression", "[rb+11]".into())])),
+            ("next", Value::object(vec![("threadId", THREAD_ID.into())])),
+            ("continue", Value::object(vec![("threadId", THREAD_ID.into())])),
+            ("disconnect", Value::Null),
+        ];
+        let (sender, receiver) = mpsc::channel();
+        for (seq, (command, arguments)) in requests.into_iter().enumerate() {
+            let request = Value::object(vec![
+                ("seq", (seq + 1).into()),
+                ("type", "request".into()),
+                ("command", command.into()),
+                ("arguments", arguments),
+            ]);
+            sender.send(Ok(request)).unwrap();
+        }
+        drop(sender);
+
+        let mut out = Vec::new();
+        Debugger::new(&mut out).serve(&receiver).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut reader = Cursor::new(out);
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message);
+        }
+        let summary: Vec<String> = messages.iter()
+            .map(|m| match m.get("type").as_str() {
+                Some("event") => format!("event {} {}", m.get("event").as_str().unwrap(), m.get("body").get("reason").as_str().unwrap_or_default()),
+                _ => format!("{} {}", m.get("command").as_str().unwrap(), m.get("success").as_bool().unwrap()),
+            })
+            .collect();
+        assert_eq!(summary, vec![
+            "initialize true", "launch true", "event initialized ", "setBreakpoints true",
+            "configurationDone true", "event stopped entry",
+            "continue true", "event stopped breakpoint",
+            "stackTrace true", "variables true", "evaluate true",
+            "next true", "event output ", "event stopped step",
+            "continue true", "event exited ", "event terminated ",
+            "disconnect true",
+        ]);
+
+        let body = |idx: usize| messages[idx].get("body").clone();
+        let breakpoints = body(3).get("breakpoints").clone();
+        assert_eq!(breakpoints.as_array().unwrap()[0].get("verified"), &Value::Bool(true));
+        assert_eq!(breakpoints.as_array().unwrap()[1].get("verified"), &Value::Bool(false));
+        let frame = body(8).get("stackFrames").as_array().unwrap()[0].clone();
+        assert_eq!(frame.get("line"), &Value::Int(3));
+        assert_eq!(frame.get("name").as_str(), Some("6: out [11]"));
+        assert_eq!(body(9).get("variables").as_array().unwrap()[0].get("value").as_str(), Some("42"));
+        assert_eq!(body(10).get("result").as_str(), Some("42"));
+        assert_eq!(body(12).get("output").as_str(), Some("42\n"));
+    }
+
+    #[test]
+    fn test_step_keeps_input_order() {
+        let code = vec![
+            1101, 0, 0, 20,  // 0: add 0, 0, [20]
+            3, 20,           // 4: in [20]
+            3, 21,           // 6: in [21]
+            99,              // 8: halt
+        ];
+        let mut target = Target {
+            program: Program::new(code.clone()),
+            name: "test".to_string(),
+            listing: Listing::new(&code, 0),
+            inputs: vec![1, 2].into(),
+            ascii: false,
+            stop_on_entry: false,
+            halted: false,
+        };
+        let (_sender, receiver) = mpsc::channel();
+        let mut out = Vec::new();
+        let mut debugger = Debugger::new(&mut out);
+        for _ in 0..3 {
+            debugger.run(&mut target, true, &receiver).unwrap();
+        }
+        assert_eq!((target.program.peek(20), target.program.peek(21)), (Some(1), Some(2)));
+        assert!(target.inputs.is_empty());
+    }
+
+    #[test]
+    fn test_breakpoint_on_entry() {
+        let code = vec![
+            3, 20,           // 0: in [20]
+            4, 20,           // 2: out [20]
+            1105, 1, 0,      // 4: goto 0
+        ];
+        let mut target = Target {
+            program: Program::new(code.clone()),
+            name: "test".to_string(),
+            listing: Listing::new(&code, 0),
+            inputs: vec![1, 2].into(),
+            ascii: false,
+            stop_on_entry: false,
+            halted: false,
+        };
+        target.program.set_breakpoints(vec![0].into_iter().collect());
+        let (_sender, receiver) = mpsc::channel();
+        let mut out = Vec::new();
+        let mut debugger = Debugger::new(&mut out);
+        // Stops on entry, then once per loop, not on the instruction it resumes from.
+        assert!(matches!(debugger.run(&mut target, false, &receiver).unwrap(), Stop::Breakpoint));
+        assert_eq!(target.program.steps(), 0);
+        assert!(matches!(debugger.run(&mut target, false, &receiver).unwrap(), Stop::Breakpoint));
+        assert_eq!(target.program.steps(), 3);
+        assert!(matches!(debugger.run(&mut target, false, &receiver).unwrap(), Stop::Breakpoint));
+        assert!(matches!(debugger.run(&mut target, false, &receiver).unwrap(), Stop::Exception(_)));
+        assert_eq!(target.program.steps(), 6);
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let path = std::env::temp_dir().join(format!("intcode-dap-range-{}.txt", std::process::id()));
+        fs::write(&path, "109,1,99").unwrap();
+        let mut debugger = Debugger::new(Vec::new());
+        let args = Value::object(vec![("program", path.to_str().unwrap().into()), ("memory", Value::Int(-1))]);
+        assert_eq!(debugger.launch(&args).unwrap_err().to_string(), "Memory size -1 is negative");
+
+        let args = Value::object(vec![("program", path.to_str().unwrap().into())]);
+        debugger.launch(&args).unwrap();
+        fs::remove_file(&path).unwrap();
+        let target = debugger.target.as_mut().unwrap();
+        target.program.run(&mut None);
+        assert_eq!(target.evaluate("[rb+1]").unwrap(), 99);
+        assert!(target.evaluate(&format!("[rb+{}]", isize::MAX)).is_err());
+    }
+}
@@ -0,0 +1,530 @@
+//! Decompiler from Intcode to structured, C-like pseudo-code.
+//!
+//! The basic blocks found by `blocks::find_blocks` are grouped into functions and every function
+//! is structured into `while` loops and `if`/`else` statements by looking at its jumps in address
+//! order. Jumps that don't fit this structure are kept as `goto`.
+//!
+//! Functions are recognised by the usual relative base calling convention: a call stores the
+//! return address into a cell relative to the relative base, usually moves the relative base and
+//! jumps to the function. A function returns with a jump to the address stored in a relative
+//! cell. Copies into the cells directly behind the return address are shown as arguments.
+//!
+//! Cells are named after how they are accessed: `global_N` for absolute address `N`, `mem[N]` if
+//! `N` lies inside the code, and relative cells after their offset from the relative base at the
+//! start of their function: `ret_addr` and `param_N` for the return address and arguments of a
+//! function, `local_N` for everything else.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{
+    blocks::{BasicBlock, Flow, find_blocks, flow},
+    intcode::{Operation, ParameterMode},
+};
+
+/// An instruction parameter as stored in `Operation`.
+type Operand = (ParameterMode, isize);
+
+/// Decompile the code reachable from `entry`.
+pub fn decompile(memory: &[isize], entry: usize) -> String {
+    let blocks = find_blocks(memory, entry);
+    let mut decompiler = Decompiler {
+        blocks: &blocks,
+        code_end: 0,
+        calls: HashMap::new(),
+        functions: BTreeMap::new(),
+        rb_delta: HashMap::new(),
+        hidden: HashSet::new(),
+        lines: Vec::new(),
+        block_lines: HashMap::new(),
+        gotos: BTreeSet::new(),
+    };
+    decompiler.find_functions(entry);
+
+    let functions: Vec<(usize, Function)> = decompiler.functions.iter().map(|(&start, f)| (start, f.clone())).collect();
+    for (start, function) in &functions {
+        decompiler.emit_function(*start, function);
+    }
+
+    // Labels are only known after all jumps were emitted.
+    let mut labels: Vec<(usize, usize, usize)> = decompiler.gotos.iter()
+        .filter_map(|target| decompiler.block_lines.get(target).map(|&(line, indent)| (line, indent, *target)))
+        .collect();
+    labels.sort_unstable();
+    for (line, indent, target) in labels.into_iter().rev() {
+        decompiler.lines.insert(line, format!("{}label_{}:", "    ".repeat(indent.saturating_sub(1)), target));
+    }
+
+    let mut out = String::new();
+    for line in &decompiler.lines {
+        writeln!(out, "{}", line).expect("writing to a string can't fail");
+    }
+    out
+}
+
+#[derive(Debug, Clone, Default)]
+struct Function {
+    blocks: BTreeSet<usize>,
+    /// Number of arguments passed by the callers, `None` if the function is never called.
+    arity: Option<usize>,
+}
+
+/// A block ending with a call.
+#[derive(Debug, Clone)]
+struct Call {
+    target: usize,
+    /// Instructions copying the arguments, in order.
+    args: Vec<usize>,
+}
+
+struct Decompiler<'a> {
+    blocks: &'a BTreeMap<usize, BasicBlock>,
+    /// Addresses below belong to code, absolute accesses to them are shown as `mem[N]`.
+    code_end: usize,
+    calls: HashMap<usize, Call>,
+    functions: BTreeMap<usize, Function>,
+    /// Relative base before every instruction, relative to its value at the start of the
+    /// function. `None` if it differs between paths or is changed by a computed value.
+    rb_delta: HashMap<usize, Option<isize>>,
+    /// Instructions that are already shown as part of a call.
+    hidden: HashSet<usize>,
+    lines: Vec<String>,
+    /// Line and indentation level at which each block starts.
+    block_lines: HashMap<usize, (usize, usize)>,
+    gotos: BTreeSet<usize>,
+}
+
+/// The value a copy instruction writes, if `op` is one.
+fn copy_source(op: &Operation) -> Option<Operand> {
+    match *op {
+        Operation::Add { left_op, right_op: (ParameterMode::Immediate, 0), .. } => Some(left_op),
+        Operation::Add { left_op: (ParameterMode::Immediate, 0), right_op, .. } => Some(right_op),
+        Operation::Mul { left_op, right_op: (ParameterMode::Immediate, 1), .. } => Some(left_op),
+        Operation::Mul { left_op: (ParameterMode::Immediate, 1), right_op, .. } => Some(right_op),
+        _ => None,
+    }
+}
+
+fn dest(op: &Operation) -> Option<Operand> {
+    match *op {
+        Operation::Add { dest_pos, .. } | Operation::Mul { dest_pos, .. } | Operation::LessThan { dest_pos, .. }
+        | Operation::Equals { dest_pos, .. } | Operation::Input { dest_pos } => Some(dest_pos),
+        _ => None,
+    }
+}
+
+/// Jump target operand and whether the jump is taken on a non-zero condition.
+fn jump_parts(op: &Operation) -> Option<(Operand, Operand, bool)> {
+    match *op {
+        Operation::JumpIfTrue { bool_param, jump_dest } => Some((bool_param, jump_dest, true)),
+        Operation::JumpIfFalse { bool_param, jump_dest } => Some((bool_param, jump_dest, false)),
+        _ => None,
+    }
+}
+
+impl<'a> Decompiler<'a> {
+    fn last_op(&self, start: usize) -> (usize, Operation) {
+        *self.blocks[&start].instructions.last().expect("blocks are never empty")
+    }
+
+    /// Recognise a call at the end of `block`, hiding the instructions it consists of.
+    fn find_call(&mut self, block: &BasicBlock) -> Option<Call> {
+        let target = match flow(&block.instructions.last()?.1) {
+            Flow::Jump { target: Some(target), conditional: false } => target,
+            _ => return None,
+        };
+        let (ret_idx, ret_slot) = block.instructions.iter().enumerate().find_map(|(idx, (_, op))| {
+            match (copy_source(op), dest(op)) {
+                (Some((ParameterMode::Immediate, value)), Some((ParameterMode::Relative, slot))) if value == block.end as isize => Some((idx, slot)),
+                _ => None,
+            }
+        })?;
+        self.hidden.insert(block.instructions[ret_idx].0);
+
+        let mut args = Vec::new();
+        while let Some(&(ip, _)) = block.instructions.iter().find(|(_, op)| {
+            copy_source(op).is_some() && dest(op) == Some((ParameterMode::Relative, ret_slot + args.len() as isize + 1))
+        }) {
+            self.hidden.insert(ip);
+            args.push(ip);
+        }
+
+        // Moving the relative base to the new frame and back belongs to the call as well.
+        let adjust = block.instructions[ret_idx..].iter().find_map(|(ip, op)| match op {
+            Operation::SetRelativeOffset { source: (ParameterMode::Immediate, offset) } => Some((*ip, *offset)),
+            _ => None,
+        });
+        if let Some((ip, offset)) = adjust {
+            if let Some(&(back_ip, Operation::SetRelativeOffset { source })) = self.blocks.get(&block.end).and_then(|b| b.instructions.first()) {
+                if source == (ParameterMode::Immediate, -offset) {
+                    self.hidden.insert(ip);
+                    self.hidden.insert(back_ip);
+                }
+            }
+        }
+        Some(Call { target, args })
+    }
+
+    fn find_functions(&mut self, entry: usize) {
+        let blocks = self.blocks;
+        let mut todo = vec![entry];
+        self.functions.insert(entry, Function::default());
+        while let Some(start) = todo.pop() {
+            let mut function = Function::default();
+            let mut pending = vec![(start, Some(0))];
+            while let Some((addr, delta)) = pending.pop() {
+                let block = match blocks.get(&addr) {
+                    Some(block) => block,
+                    None => continue,
+                };
+                // Paths disagreeing about the relative base make it unknown.
+                let mut delta = delta;
+                if !function.blocks.insert(addr) {
+                    let known = self.rb_delta[&addr];
+                    if known == delta || known.is_none() {
+                        continue;
+                    }
+                    delta = None;
+                }
+                self.code_end = self.code_end.max(block.end);
+
+                let mut current = delta;
+                for (ip, op) in &block.instructions {
+                    self.rb_delta.insert(*ip, current);
+                    if let Operation::SetRelativeOffset { source } = op {
+                        current = match source {
+                            (ParameterMode::Immediate, offset) => current.map(|d| d + offset),
+                            _ => None,
+                        };
+                    }
+                }
+
+                if let Some(call) = self.find_call(block) {
+                    let arity = &mut self.functions.entry(call.target).or_insert_with(|| {
+                        todo.push(call.target);
+                        Function::default()
+                    }).arity;
+                    *arity = Some(arity.unwrap_or(0).max(call.args.len()));
+                    self.calls.insert(addr, call);
+                    pending.push((block.end, current));
+                } else {
+                    pending.extend(block.successors.iter().map(|&succ| (succ, current)));
+                }
+            }
+            let arity = self.functions[&start].arity;
+            self.functions.insert(start, Function { arity, ..function });
+        }
+    }
+
+    fn name(&self, ip: usize, (mode, value): Operand, function: &Function) -> String {
+        match mode {
+            ParameterMode::Immediate => value.to_string(),
+            ParameterMode::Position if value >= 0 && (value as usize) < self.code_end => format!("mem[{}]", value),
+            ParameterMode::Position => format!("global_{}", value),
+            ParameterMode::Relative => match self.rb_delta.get(&ip).copied().flatten() {
+                Some(delta) => match delta + value {
+                    0 if function.arity.is_some() => "ret_addr".to_string(),
+                    slot if slot > 0 && slot as usize <= function.arity.unwrap_or(0) => format!("param_{}", slot),
+                    slot if slot < 0 => format!("local_m{}", -slot),
+                    slot => format!("local_{}", slot),
+                },
+                None => format!("rb[{}]", value),
+            },
+        }
+    }
+
+    fn line(&mut self, indent: usize, text: String) {
+        self.lines.push(format!("{}{}", "    ".repeat(indent), text));
+    }
+
+    fn emit_function(&mut self, start: usize, function: &Function) {
+        let params: Vec<String> = (1..=function.arity.unwrap_or(0)).map(|n| format!("param_{}", n)).collect();
+        if !self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+        self.line(0, format!("func_{}({}) {{", start, params.join(", ")));
+        let end = function.blocks.iter().map(|b| self.blocks[b].end).max().unwrap_or(start);
+        self.emit_range(function, start, end, &[], 1, None);
+        self.line(0, "}".to_string());
+    }
+
+    /// The statement for a jump to `target` inside the loops `loops`, innermost last.
+    fn jump_stmt(&mut self, target: usize, loops: &[(usize, usize)]) -> String {
+        match loops.last() {
+            Some(&(header, _)) if header == target => "continue;".to_string(),
+            Some(&(_, exit)) if exit == target => "break;".to_string(),
+            _ => {
+                self.gotos.insert(target);
+                format!("goto label_{};", target)
+            }
+        }
+    }
+
+    fn is_loop_target(target: usize, loops: &[(usize, usize)]) -> bool {
+        loops.last().is_some_and(|&(header, exit)| target == header || target == exit)
+    }
+
+    /// The condition under which the jump at `ip` is taken, or not taken if `negate` is set.
+    fn condition(&self, ip: usize, op: &Operation, negate: bool, function: &Function) -> String {
+        let (cond, _, if_true) = jump_parts(op).expect("only called for jumps");
+        let name = self.name(ip, cond, function);
+        if if_true != negate { name } else { format!("!{}", name) }
+    }
+
+    /// Emit all blocks of `function` that start in `lo..hi`. Loops are only detected with a header
+    /// other than `in_loop`, the header of the loop currently being emitted.
+    fn emit_range(&mut self, function: &Function, lo: usize, hi: usize, loops: &[(usize, usize)], indent: usize, in_loop: Option<usize>) {
+        let mut addr = lo;
+        while let Some(&start) = function.blocks.range(addr..hi).next() {
+            if in_loop != Some(start) {
+                if let Some(exit) = self.loop_exit(function, start, hi) {
+                    self.emit_loop(function, start, exit, loops, indent);
+                    addr = exit;
+                    continue;
+                }
+            }
+            addr = self.emit_block(function, start, hi, loops, indent);
+        }
+    }
+
+    /// End of the loop with its header at `start`: the end of the last block in `start..hi`
+    /// jumping back to it.
+    fn loop_exit(&self, function: &Function, start: usize, hi: usize) -> Option<usize> {
+        function.blocks.range(start..hi).rev()
+            .map(|b| &self.blocks[b])
+            .find(|b| b.end <= hi && !self.calls.contains_key(&b.start) && matches!(flow(&b.instructions.last().unwrap().1), Flow::Jump { target: Some(t), .. } if t == start))
+            .map(|b| b.end)
+    }
+
+    fn emit_loop(&mut self, function: &Function, start: usize, exit: usize, loops: &[(usize, usize)], indent: usize) {
+        let mut inner = loops.to_vec();
+        inner.push((start, exit));
+        let blocks = self.blocks;
+        let header = &blocks[&start];
+        let (ip, op) = self.last_op(start);
+
+        // A header that only checks the loop condition becomes a `while` condition.
+        let simple = header.instructions.len() == 1 && matches!(flow(&op), Flow::Jump { target: Some(t), conditional: true } if t == exit);
+        self.block_lines.insert(start, (self.lines.len(), indent));
+        if simple {
+            let cond = self.condition(ip, &op, true, function);
+            self.line(indent, format!("while ({}) {{", cond));
+            self.emit_range(function, header.end, exit, &inner, indent + 1, None);
+        } else {
+            self.line(indent, "while (1) {".to_string());
+            self.emit_range(function, start, exit, &inner, indent + 1, Some(start));
+        }
+
+        // Leave the loop if its last block falls through to the exit.
+        let last = function.blocks.range(start..exit).next_back().map(|b| &blocks[b]);
+        if let Some(last) = last {
+            let falls_through = match flow(&self.last_op(last.start).1) {
+                Flow::Next | Flow::Jump { conditional: true, .. } => true,
+                Flow::Jump { conditional: false, .. } | Flow::Halt => self.calls.contains_key(&last.start),
+            };
+            if falls_through && !(simple && last.start == start) {
+                self.line(indent + 1, "break;".to_string());
+            }
+        }
+        self.line(indent, "}".to_string());
+    }
+
+    /// Emit the block at `start`, including the `if` statement it starts. Returns the address
+    /// to continue at.
+    fn emit_block(&mut self, function: &Function, start: usize, hi: usize, loops: &[(usize, usize)], indent: usize) -> usize {
+        let blocks = self.blocks;
+        let block = &blocks[&start];
+        self.block_lines.insert(start, (self.lines.len(), indent));
+        let (last_ip, last_op) = self.last_op(start);
+        for &(ip, op) in &block.instructions {
+            if self.hidden.contains(&ip) || jump_parts(&op).is_some() {
+                continue;
+            }
+            let stmt = self.statement(ip, &op, function);
+            self.line(indent, stmt);
+        }
+        if self.hidden.contains(&last_ip) {
+            return block.end;
+        }
+
+        if let Some(call) = self.calls.get(&start) {
+            let args: Vec<String> = call.args.iter()
+                .map(|ip| {
+                    let op = self.blocks[&start].instructions.iter().find(|(i, _)| i == ip).unwrap().1;
+                    self.name(*ip, copy_source(&op).unwrap(), function)
+                })
+                .collect();
+            let stmt = format!("func_{}({});", call.target, args.join(", "));
+            self.line(indent, stmt);
+            return block.end;
+        }
+
+        match flow(&last_op) {
+            Flow::Next | Flow::Halt => block.end,
+            Flow::Jump { target: Some(target), conditional: true } if target > block.end && target <= hi && !Self::is_loop_target(target, loops) => {
+                self.emit_if(function, block.end, target, hi, (last_ip, last_op), loops, indent)
+            }
+            Flow::Jump { target, conditional } => {
+                let stmt = match target {
+                    // A jump back to the header at the end of a loop body is implied.
+                    Some(target) if !conditional && block.end == hi && loops.last().is_some_and(|&(header, _)| header == target) => return block.end,
+                    Some(target) => self.jump_stmt(target, loops),
+                    None => {
+                        let (_, dest, _) = jump_parts(&last_op).expect("block ends with a jump");
+                        match dest.0 {
+                            ParameterMode::Relative if !conditional => "return;".to_string(),
+                            _ => format!("goto *{};", self.name(last_ip, dest, function)),
+                        }
+                    }
+                };
+                if conditional {
+                    let cond = self.condition(last_ip, &last_op, false, function);
+                    self.line(indent, format!("if ({}) {}", cond, stmt));
+                } else {
+                    self.line(indent, stmt);
+                }
+                block.end
+            }
+        }
+    }
+
+    /// Emit an `if` for the conditional jump `jump` to `target`, skipping the code from `then`.
+    #[allow(clippy::too_many_arguments)]
+    fn emit_if(&mut self, function: &Function, then: usize, target: usize, hi: usize, jump: (usize, Operation), loops: &[(usize, usize)], indent: usize) -> usize {
+        let cond = self.condition(jump.0, &jump.1, true, function);
+        self.line(indent, format!("if ({}) {{", cond));
+
+        // The then branch ends with a jump over the else branch.
+        let else_end = function.blocks.range(then..target).next_back()
+            .filter(|b| !self.calls.contains_key(b))
+            .and_then(|b| {
+                let (ip, op) = self.last_op(*b);
+                match flow(&op) {
+                    Flow::Jump { target: Some(end), conditional: false } if end > target && end <= hi && !Self::is_loop_target(end, loops) => Some((ip, end)),
+                    _ => None,
+                }
+            });
+
+        match else_end {
+            Some((jump_ip, end)) => {
+                self.hidden.insert(jump_ip);
+                self.emit_range(function, then, target, loops, indent + 1, None);
+                self.line(indent, "} else {".to_string());
+                self.emit_range(function, target, end, loops, indent + 1, None);
+                self.line(indent, "}".to_string());
+                end
+            }
+            None => {
+                self.emit_range(function, then, target, loops, indent + 1, None);
+                self.line(indent, "}".to_string());
+                target
+            }
+        }
+    }
+
+    fn statement(&self, ip: usize, op: &Operation, function: &Function) -> String {
+        let name = |operand| self.name(ip, operand, function);
+        let binary = |left, symbol: &str, right, dest| format!("{} = {} {} {};", name(dest), name(left), symbol, name(right));
+        if let (Some(source), Some(dest)) = (copy_source(op), dest(op)) {
+            return format!("{} = {};", name(dest), name(source));
+        }
+        match *op {
+            Operation::Add { left_op, right_op: (ParameterMode::Immediate, value), dest_pos } if value < 0 => {
+                format!("{} = {} - {};", name(dest_pos), name(left_op), -value)
+            }
+            Operation::Add { left_op, right_op, dest_pos } => binary(left_op, "+", right_op, dest_pos),
+            Operation::Mul { left_op, right_op: (ParameterMode::Immediate, -1), dest_pos } => format!("{} = -{};", name(dest_pos), name(left_op)),
+            Operation::Mul { left_op, right_op, dest_pos } => binary(left_op, "*", right_op, dest_pos),
+            Operation::LessThan { left_op, right_op, dest_pos } => binary(left_op, "<", right_op, dest_pos),
+            Operation::Equals { left_op, right_op, dest_pos } => binary(left_op, "==", right_op, dest_pos),
+            Operation::Input { dest_pos } => format!("{} = input();", name(dest_pos)),
+            Operation::Output { inp_pos } => format!("output({});", name(inp_pos)),
+            Operation::SetRelativeOffset { source: (ParameterMode::Immediate, value) } if value < 0 => format!("rb -= {};", -value),
+            Operation::SetRelativeOffset { source } => format!("rb += {};", name(source)),
+            Operation::Halt => "halt();".to_string(),
+            Operation::JumpIfTrue { .. } | Operation::JumpIfFalse { .. } => unreachable!("jumps are emitted as control flow"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+
+    #[test]
+    fn test_structure() {
+        let prog = [
+            3, 30,              // 0: [30] = input
+            1008, 30, 5, 31,    // 2: [31] = [30] == 5
+            1006, 31, 15,       // 6: if ![31] goto 15
+            104, 1,             // 9: output 1
+            1105, 1, 17,        // 11: goto 17
+            99,                 // 14: unused
+            104, 2,             // 15: output 2
+            1001, 30, -1, 30,   // 17: [30] -= 1
+            1005, 30, 2,        // 21: if [30] goto 2
+            99,                 // 24: halt
+        ];
+        let code = decompile(&prog, 0);
+        assert_eq!(code, "\
+func_0() {
+    global_30 = input();
+    while (1) {
+        global_31 = global_30 == 5;
+        if (global_31) {
+            output(1);
+        } else {
+            output(2);
+        }
+        global_30 = global_30 - 1;
+        if (global_30) continue;
+        break;
+    }
+    halt();
+}
+");
+    }
+
+    #[test]
+    fn test_compiled() {
+        let source = "
+            fn sum(a, b) {
+                return a + b;
+            }
+
+            fn main() {
+                let i = input();
+                while i < 10 {
+                    output(sum(i, 3));
+                    i = i + 1;
+                }
+            }
+        ";
+        let code = decompile(&compile(source).unwrap(), 0);
+        assert!(!code.contains("goto"), "{}", code);
+        assert!(code.contains("param_1, param_2) {"), "{}", code);
+        assert!(code.contains(" = param_1 + param_2;"), "{}", code);
+        assert!(code.contains("    return;\n}"), "{}", code);
+        assert!(code.contains("        if (!local_2) break;\n"), "{}", code);
+        assert!(code.contains("(local_1, 3);\n"), "{}", code);
+        assert!(!code.contains("rb -="), "{}", code);
+    }
+
+    #[test]
+    fn test_goto() {
+        // A jump into the middle of a loop body can't be structured.
+        let prog = [
+            3, 20,          // 0: input
+            104, 1,         // 2: output 1, loop header
+            104, 2,         // 4: output 2
+            1005, 20, 2,    // 6: if [20] goto 2
+            1105, 1, 4,     // 9: goto 4, into the loop body
+        ];
+        let code = decompile(&prog, 0);
+        assert!(code.contains("    while (1) {\n        output(1);\n    label_4:\n        output(2);\n"), "{}", code);
+        assert!(code.contains("    }\n    goto label_4;\n"), "{}", code);
+    }
+}
@@ -0,0 +1,197 @@
+//! Memory mapped peripheral devices.
+//!
+//! A device is mapped onto a range of absolute addresses of a `Program`. Every parameter read
+//! from or result written to one of these addresses is handled by the device instead of plain
+//! memory, the device receives the offset of the address inside its range.
+//!
+//! Addresses are always resolved before the device lookup: a relative mode parameter first adds
+//! the relative base and the resulting absolute address is dispatched. A device is therefore
+//! reached at the same address regardless of the current relative base, and moving the relative
+//! base never moves a device. The relative base itself is a register and not mapped anywhere.
+//!
+//! Instructions are always fetched from plain memory, devices are not executable.
+
+use std::{
+    cell::RefCell,
+    io::Write,
+    rc::Rc,
+};
+
+pub trait Device {
+    /// Read the value at `offset` into the mapped range.
+    fn read(&mut self, offset: usize) -> isize;
+
+    /// Write `value` at `offset` into the mapped range.
+    fn write(&mut self, offset: usize, value: isize);
+}
+
+impl<D: Device + ?Sized> Device for Rc<RefCell<D>> {
+    fn read(&mut self, offset: usize) -> isize {
+        self.borrow_mut().read(offset)
+    }
+
+    fn write(&mut self, offset: usize, value: isize) {
+        self.borrow_mut().write(offset, value)
+    }
+}
+
+/// A counter that advances by one on every read. Writing sets the counter.
+#[derive(Debug, Default)]
+pub struct Clock {
+    pub ticks: isize,
+}
+
+impl Device for Clock {
+    fn read(&mut self, _offset: usize) -> isize {
+        let ticks = self.ticks;
+        self.ticks += 1;
+        ticks
+    }
+
+    fn write(&mut self, _offset: usize, value: isize) {
+        self.ticks = value;
+    }
+}
+
+/// Deterministic xorshift random number generator. Every read returns a new non-negative
+/// number, writing reseeds the generator.
+#[derive(Debug)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: isize) -> Self {
+        let mut random = Random { state: 0 };
+        random.write(0, seed);
+        random
+    }
+}
+
+impl Device for Random {
+    fn read(&mut self, _offset: usize) -> isize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 1) as isize
+    }
+
+    fn write(&mut self, _offset: usize, value: isize) {
+        const MIX: u64 = 0x2545_f491_4f6c_dd1d;
+        self.state = (value as u64) ^ MIX;
+        // xorshift gets stuck on a state of 0.
+        if self.state == 0 {
+            self.state = MIX;
+        }
+    }
+}
+
+/// Writes every value as ASCII character to the wrapped writer. Reads return the number of
+/// characters written so far.
+pub struct Console<W: Write> {
+    writer: W,
+    written: isize,
+}
+
+impl<W: Write> Console<W> {
+    pub fn new(writer: W) -> Self {
+        Console { writer, written: 0 }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Device for Console<W> {
+    fn read(&mut self, _offset: usize) -> isize {
+        self.written
+    }
+
+    fn write(&mut self, _offset: usize, value: isize) {
+        let c = if (0..128).contains(&value) { value as u8 } else { b'?' };
+        // A device has no way to report errors to the program, a broken console just drops output.
+        if self.writer.write_all(&[c]).and_then(|_| self.writer.flush()).is_ok() {
+            self.written += 1;
+        }
+    }
+}
+
+/// A `width` x `height` grid of cells, stored row by row.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FrameBuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<isize>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        FrameBuffer { width, height, pixels: vec![0; width * height] }
+    }
+
+    pub fn size(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> isize {
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Device for FrameBuffer {
+    fn read(&mut self, offset: usize) -> isize {
+        self.pixels[offset]
+    }
+
+    fn write(&mut self, offset: usize, value: isize) {
+        self.pixels[offset] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Program, ProgramState};
+
+    #[test]
+    fn test_clock_and_random() {
+        let mut clock = Clock::default();
+        assert_eq!((clock.read(0), clock.read(0)), (0, 1));
+        clock.write(0, 10);
+        assert_eq!(clock.read(0), 10);
+
+        let mut a = Random::new(42);
+        let mut b = Random::new(42);
+        let values: Vec<isize> = (0..10).map(|_| a.read(0)).collect();
+        assert!(values.iter().all(|&v| v >= 0));
+        assert_eq!(values, (0..10).map(|_| b.read(0)).collect::<Vec<_>>());
+        b.write(0, 42);
+        assert_eq!(b.read(0), values[0]);
+    }
+
+    #[test]
+    fn test_mapped_program() {
+        let prog = vec![
+            109, 1000,              // relative base = 1000
+            1001, 1000, 65, 1001,   // console = clock + 'A'
+            1001, 1000, 65, 1001,   // console = clock + 'A'
+            21101, 7, 8, 3,         // [relative base + 3] = 7 + 8
+            99,
+        ];
+        let mut prog = Program::with_memory_size(prog, 100);
+        let console = Rc::new(RefCell::new(Console::new(Vec::new())));
+        let frame = Rc::new(RefCell::new(FrameBuffer::new(2, 2)));
+        prog.map_device(1000..1001, Box::new(Clock::default())).unwrap();
+        prog.map_device(1001..1002, Box::new(console.clone())).unwrap();
+        prog.map_device(1002..1006, Box::new(frame.clone())).unwrap();
+        assert!(prog.map_device(1005..1007, Box::new(Random::new(0))).is_err());
+        assert!(prog.map_device(1006..1006, Box::new(Random::new(0))).is_err());
+
+        assert_eq!(prog.run(&mut None).0, ProgramState::Halt);
+        assert_eq!(console.borrow().writer, b"AB");
+        assert_eq!(console.borrow().written, 2);
+        assert_eq!(frame.borrow().pixels, vec![0, 15, 0, 0]);
+        assert_eq!(frame.borrow().get(1, 0), 15);
+    }
+}
@@ -0,0 +1,73 @@
+//! Instructions that can be added to a program with `Program::register_instruction`.
+
+use std::io::Write;
+
+use crate::{crash::Fault, intcode::{Context, Effect, Instruction}};
+
+/// Prints the value of its single parameter together with the instruction pointer.
+pub struct DebugPrint<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> DebugPrint<W> {
+    pub fn new(writer: W) -> Self {
+        DebugPrint { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Instruction for DebugPrint<W> {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn eval(&mut self, context: &mut Context) -> Result<Effect, Fault> {
+        let value = context.read(0)?;
+        let _ = writeln!(self.writer, "debug at {}: {}", context.instruction_ptr(), value);
+        Ok(Effect::Continue)
+    }
+}
+
+/// Stops the program with `ProgramState::Trap`.
+#[derive(Debug, Default)]
+pub struct Trap;
+
+impl Instruction for Trap {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn eval(&mut self, _context: &mut Context) -> Result<Effect, Fault> {
+        Ok(Effect::Trap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::intcode::{Program, ProgramState};
+
+    #[test]
+    fn test_extensions() {
+        let code = vec![
+            1101, 2, 3, 11, // 0: [11] = 5
+            151, 7,         // 4: print 7
+            51, 11,         // 6: print [11]
+            50,             // 8: trap
+            99,
+            0, 0,
+        ];
+        let print = Rc::new(RefCell::new(DebugPrint::new(Vec::new())));
+        let mut prog = Program::new(code);
+        prog.register_instruction(50, Box::new(Trap)).unwrap();
+        prog.register_instruction(51, Box::new(print.clone())).unwrap();
+        assert_eq!(prog.run(&mut None).0, ProgramState::Trap);
+        assert_eq!(prog.run(&mut None).0, ProgramState::Halt);
+        assert_eq!(String::from_utf8_lossy(&print.borrow().writer), "debug at 4: 7\ndebug at 6: 5\n");
+    }
+}
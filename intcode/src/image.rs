@@ -0,0 +1,180 @@
+//! Compact binary format for Intcode program images.
+//!
+//! An image starts with a fixed header, all numbers in little endian:
+//!
+//! | offset | size | content                                 |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 4    | magic `b"ICIM"`                         |
+//! | 4      | 1    | format version, currently 1             |
+//! | 5      | 1    | word size of the cells in bits          |
+//! | 6      | 8    | entry point (initial instruction ptr)   |
+//! | 14     | 8    | number of cells                         |
+//!
+//! After the header, every cell is stored zig-zag encoded as an unsigned LEB128 varint, so small
+//! positive and negative numbers only take a single byte.
+
+use std::convert::TryFrom;
+
+use crate::Error;
+
+pub const MAGIC: &[u8; 4] = b"ICIM";
+pub const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 22;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Image {
+    pub entry_point: usize,
+    pub memory: Vec<isize>,
+}
+
+impl Image {
+    pub fn new(memory: Vec<isize>) -> Self {
+        Image { entry_point: 0, memory }
+    }
+
+    pub fn is_image(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    pub fn parse_text(text: &str) -> Result<Self, Error> {
+        let memory: Result<Vec<isize>, _> = text.split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(str::parse::<isize>)
+            .collect();
+        Ok(Image::new(memory?))
+    }
+
+    /// Read either a binary image or the comma separated text format.
+    pub fn load(bytes: &[u8]) -> Result<Self, Error> {
+        if Image::is_image(bytes) {
+            Image::decode(bytes)
+        } else {
+            Image::parse_text(std::str::from_utf8(bytes)?)
+        }
+    }
+
+    /// Format as comma separated text. The text format has no entry point, so it has to be 0.
+    pub fn to_text(&self) -> Result<String, Error> {
+        if self.entry_point != 0 {
+            return Err(format!("Entry point {} can not be represented in text format", self.entry_point).into());
+        }
+        let cells: Vec<String> = self.memory.iter().map(isize::to_string).collect();
+        Ok(format!("{}\n", cells.join(",")))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.memory.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push((std::mem::size_of::<isize>() * 8) as u8);
+        bytes.extend_from_slice(&(self.entry_point as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.memory.len() as u64).to_le_bytes());
+        for &cell in &self.memory {
+            write_varint(&mut bytes, zigzag_encode(cell as i64));
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE || !Image::is_image(bytes) {
+            return Err("Not an Intcode image".into());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("Unsupported image version {}", bytes[4]).into());
+        }
+        let word_size = bytes[5];
+        if word_size == 0 || word_size > 64 {
+            return Err(format!("Unsupported word size {}", word_size).into());
+        }
+        let entry_point = usize::try_from(read_u64(&bytes[6..14]))?;
+        let len = usize::try_from(read_u64(&bytes[14..22]))?;
+
+        let mut rest = &bytes[HEADER_SIZE..];
+        // Every cell takes at least one byte, don't trust the header for the allocation.
+        let mut memory = Vec::with_capacity(len.min(rest.len()));
+        for idx in 0..len {
+            let value = read_varint(&mut rest).ok_or_else(|| format!("Image truncated at cell {}", idx))?;
+            memory.push(isize::try_from(zigzag_decode(value))?);
+        }
+        if !rest.is_empty() {
+            return Err(format!("{} trailing bytes after the last cell", rest.len()).into());
+        }
+
+        Ok(Image { entry_point, memory })
+    }
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (idx, &byte) in bytes.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * idx);
+        if byte & 0x80 == 0 {
+            *bytes = &bytes[idx + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zigzag() {
+        for &(value, encoded) in &[(0, 0), (-1, 1), (1, 2), (-2, 3), (i64::MAX, u64::MAX - 1), (i64::MIN, u64::MAX)] {
+            assert_eq!(zigzag_encode(value), encoded);
+            assert_eq!(zigzag_decode(encoded), value);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99,1125899906842624,-1125899906842624\n";
+        let image = Image::parse_text(text).unwrap();
+        let bytes = image.encode();
+        assert_eq!(bytes.len(), HEADER_SIZE + 43);
+        let decoded = Image::load(&bytes).unwrap();
+        assert_eq!(decoded, image);
+        assert_eq!(decoded.to_text().unwrap(), text);
+
+        let image = Image { entry_point: 4, memory: vec![isize::MIN, isize::MAX, 0] };
+        assert_eq!(Image::decode(&image.encode()).unwrap(), image);
+        assert!(image.to_text().is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = Image::new(vec![1, 2, 300]).encode();
+        assert!(Image::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Image::decode(&[&bytes[..], &[0]].concat()).is_err());
+        assert!(Image::decode(b"1,2,3").is_err());
+
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 2;
+        assert!(Image::decode(&bad_version).is_err());
+    }
+}
@@ -0,0 +1,1549 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::Range,
+    rc::Rc,
+};
+
+use crate::{
+    Error,
+    crash::{CrashReport, Fault},
+    device::Device,
+    session::{Event, Session},
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParameterMode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl ParameterMode {
+    fn try_decode(num: isize) -> Result<Self, DecodeError> {
+        use ParameterMode::*;
+        match num {
+            0 => Ok(Position),
+            1 => Ok(Immediate),
+            2 => Ok(Relative),
+            _ => Err(DecodeError::UnknownMode(num)),
+        }
+    }
+
+    fn fetch<O: Observer + ?Sized>(&self, param: isize, base_ptr: isize, mem: &mut Memory, observer: &mut O) -> Result<isize, Fault> {
+        use ParameterMode::*;
+        let addr = match self {
+            Position => param,
+            Relative => base_ptr.wrapping_add(param),
+            Immediate => return Ok(param),
+        };
+        let addr = mem.address(addr)?;
+        let value = mem.load(addr);
+        observer.read(addr, value);
+        Ok(value)
+    }
+
+    fn fetch_addr(&self, param: isize, base_ptr: isize, mem: &Memory) -> Result<usize, Fault> {
+        use ParameterMode::*;
+        let addr = match self {
+            Position => param,
+            Relative => base_ptr.wrapping_add(param),
+            Immediate => return Err(Fault::WriteInImmediateMode),
+        };
+        mem.address(addr)
+    }
+}
+
+/// Reasons why memory does not contain a valid instruction.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DecodeError {
+    UnknownInstruction(isize),
+    UnknownMode(isize),
+    /// The instruction needs more parameters than there is memory left.
+    Truncated,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnknownInstruction(x) => write!(f, "Unknown instruction {}", x),
+            DecodeError::UnknownMode(x) => write!(f, "Unknown parameter mode {}", x),
+            DecodeError::Truncated => write!(f, "Instruction truncated by end of memory"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Operation {
+    Add {
+        left_op: (ParameterMode, isize),
+        right_op: (ParameterMode, isize),
+        dest_pos: (ParameterMode, isize),
+    },
+    Mul {
+        left_op: (ParameterMode, isize),
+        right_op: (ParameterMode, isize),
+        dest_pos: (ParameterMode, isize),
+    },
+    Input { dest_pos: (ParameterMode, isize) },
+    Output { inp_pos: (ParameterMode, isize) },
+    JumpIfTrue { bool_param: (ParameterMode, isize), jump_dest: (ParameterMode, isize) },
+    JumpIfFalse { bool_param: (ParameterMode, isize), jump_dest: (ParameterMode, isize) },
+    LessThan {
+        left_op: (ParameterMode, isize),
+        right_op: (ParameterMode, isize),
+        dest_pos: (ParameterMode, isize),
+    },
+    Equals {
+        left_op: (ParameterMode, isize),
+        right_op: (ParameterMode, isize),
+        dest_pos: (ParameterMode, isize),
+    },
+    SetRelativeOffset { source: (ParameterMode, isize) },
+    Halt,
+}
+
+enum EvalResult {
+    Halt,
+    Continue,
+    SetInstructionPtr(usize),
+    UpdateRelativeOffset(isize),
+    InputAt(usize),
+    Output(isize),
+}
+
+fn store<O: Observer + ?Sized>(mem: &mut Memory, addr: usize, value: isize, observer: &mut O) {
+    let old = mem.store(addr, value);
+    observer.write(addr, old, value);
+}
+
+impl Operation {
+    pub fn size(&self) -> usize {
+        use Operation::*;
+        match self {
+            Add { .. } => 4,
+            Mul { .. } => 4,
+            Input { .. } => 2,
+            Output { .. } => 2,
+            JumpIfTrue { .. } => 3,
+            JumpIfFalse { .. } => 3,
+            LessThan { .. } => 4,
+            Equals { .. } => 4,
+            SetRelativeOffset { .. } => 2,
+            Halt => 1,
+        }
+    }
+
+    /// Decode the instruction at the start of `mem`.
+    pub fn try_decode(mem: &[isize]) -> Result<Self, DecodeError> {
+        let instruction = *mem.first().ok_or(DecodeError::Truncated)?;
+        let arg = |idx: usize| mem.get(idx).copied().ok_or(DecodeError::Truncated);
+        let op = instruction % 100;
+        use Operation::*;
+        Ok(match op {
+            1 => {
+                let lmode = ParameterMode::try_decode((instruction / 100) % 10)?;
+                let rmode = ParameterMode::try_decode((instruction / 1000) % 10)?;
+                let dmode = ParameterMode::try_decode((instruction / 10_000) % 10)?;
+                Add {
+                    left_op: (lmode, arg(1)?),
+                    right_op: (rmode, arg(2)?),
+                    dest_pos: (dmode, arg(3)?),
+                }
+            }
+            2 => {
+                let lmode = ParameterMode::try_decode((instruction / 100) % 10)?;
+                let rmode = ParameterMode::try_decode((instruction / 1000) % 10)?;
+                let dmode = ParameterMode::try_decode((instruction / 10_000) % 10)?;
+
+                Mul {
+                    left_op: (lmode, arg(1)?),
+                    right_op: (rmode, arg(2)?),
+                    dest_pos: (dmode, arg(3)?),
+                }
+            }
+            3 => {
+                let dmode = ParameterMode::try_decode((instruction / 100) % 10)?;
+
+                Input { dest_pos: (dmode, arg(1)?) }
+            }
+            4 => {
+                let opmode = ParameterMode::try_decode((instruction / 100) % 10)?;
+
+                Output { inp_pos: (opmode, arg(1)?) }
+            }
+            5 => {
+                let bmode = ParameterMode::try_decode((instruction / 100) % 10)?;
+                let dmode = ParameterMode::try_decode((instruction / 1000) % 10)?;
+
+                JumpIfTrue {
+                    bool_param: (bmode, arg(1)?),
+                    jump_dest: (dmode, arg(2)?),
+                }
+            }
+            6 => {
+                let bmode = ParameterMode::try_decode((instruction / 100) % 10)?;
+                let dmode = ParameterMode::try_decode((instruction / 1000) % 10)?;
+
+                JumpIfFalse {
+                    bool_param: (bmode, arg(1)?),
+                    jump_dest: (dmode, arg(2)?),
+                }
+            }
+            7 => {
+                let lmode = ParameterMode::try_decode((instruction / 100) % 10)?;
+                let rmode = ParameterMode::try_decode((instruction / 1000) % 10)?;
+                let dmode = ParameterMode::try_decode((instruction / 10_000) % 10)?;
+                LessThan {
+                    left_op: (lmode, arg(1)?),
+                    right_op: (rmode, arg(2)?),
+                    dest_pos: (dmode, arg(3)?),
+                }
+            }
+            8 => {
+                let lmode = ParameterMode::try_decode((instruction / 100) % 10)?;
+                let rmode = ParameterMode::try_decode((instruction / 1000) % 10)?;
+                let dmode = ParameterMode::try_decode((instruction / 10_000) % 10)?;
+                Equals {
+                    left_op: (lmode, arg(1)?),
+                    right_op: (rmode, arg(2)?),
+                    dest_pos: (dmode, arg(3)?),
+                }
+            }
+            9 => {
+                let smode = ParameterMode::try_decode((instruction / 100) % 10)?;
+
+                SetRelativeOffset {
+                    source: (smode, arg(1)?),
+                }
+            }
+            99 => Halt,
+            x => return Err(DecodeError::UnknownInstruction(x)),
+        })
+    }
+
+    fn eval<O: Observer>(self, mem: &mut Memory, base_ptr: isize, observer: &mut O) -> Result<EvalResult, Fault> {
+        use Operation::*;
+        Ok(match self {
+            Add { left_op, right_op, dest_pos } => {
+                let (lmode, lparam) = left_op;
+                let (rmode, rparam) = right_op;
+                let (dmode, dval) = dest_pos;
+                let dest_pos = dmode.fetch_addr(dval, base_ptr, mem)?;
+                let new_val = lmode.fetch(lparam, base_ptr, mem, observer)? + rmode.fetch(rparam, base_ptr, mem, observer)?;
+                store(mem, dest_pos, new_val, observer);
+                EvalResult::Continue
+            }
+            Mul { left_op, right_op, dest_pos } => {
+                let (lmode, lparam) = left_op;
+                let (rmode, rparam) = right_op;
+                let (dmode, dval) = dest_pos;
+                let dest_pos = dmode.fetch_addr(dval, base_ptr, mem)?;
+                let new_val = lmode.fetch(lparam, base_ptr, mem, observer)? * rmode.fetch(rparam, base_ptr, mem, observer)?;
+                store(mem, dest_pos, new_val, observer);
+                EvalResult::Continue
+            }
+            Input { dest_pos } => {
+                let (dmode, dval) = dest_pos;
+                EvalResult::InputAt(dmode.fetch_addr(dval, base_ptr, mem)?)
+            }
+            Output { inp_pos: dest_pos } => {
+                let (dmode, dparam) = dest_pos;
+                EvalResult::Output(dmode.fetch(dparam, base_ptr, mem, observer)?)
+            }
+            Halt => EvalResult::Halt,
+            JumpIfTrue { bool_param, jump_dest } => {
+                let (bmode, baddr) = bool_param;
+                if bmode.fetch(baddr, base_ptr, mem, observer)? != 0 {
+                    let (jmode, jaddr) = jump_dest;
+                    EvalResult::SetInstructionPtr(jmode.fetch(jaddr, base_ptr, mem, observer)? as usize)
+                } else {
+                    EvalResult::Continue
+                }
+            }
+            JumpIfFalse { bool_param, jump_dest } => {
+                let (bmode, baddr) = bool_param;
+                if bmode.fetch(baddr, base_ptr, mem, observer)? == 0 {
+                    let (jmode, jaddr) = jump_dest;
+                    EvalResult::SetInstructionPtr(jmode.fetch(jaddr, base_ptr, mem, observer)? as usize)
+                } else {
+                    EvalResult::Continue
+                }
+            }
+            LessThan { left_op, right_op, dest_pos } => {
+                let (lmode, lparam) = left_op;
+                let (rmode, rparam) = right_op;
+                let (dmode, dval) = dest_pos;
+                let dest_pos = dmode.fetch_addr(dval, base_ptr, mem)?;
+                let new_val = lmode.fetch(lparam, base_ptr, mem, observer)? < rmode.fetch(rparam, base_ptr, mem, observer)?;
+                store(mem, dest_pos, if new_val { 1 } else { 0 }, observer);
+                EvalResult::Continue
+            }
+            Equals { left_op, right_op, dest_pos } => {
+                let (lmode, lparam) = left_op;
+                let (rmode, rparam) = right_op;
+                let (dmode, dval) = dest_pos;
+                let dest_pos = dmode.fetch_addr(dval, base_ptr, mem)?;
+                let new_val = lmode.fetch(lparam, base_ptr, mem, observer)? == rmode.fetch(rparam, base_ptr, mem, observer)?;
+                store(mem, dest_pos, if new_val { 1 } else { 0 }, observer);
+                EvalResult::Continue
+            }
+            SetRelativeOffset { source } => {
+                let (smode, sval) = source;
+                let new_val = smode.fetch(sval, base_ptr, mem, observer)?;
+                EvalResult::UpdateRelativeOffset(new_val)
+            }
+        })
+    }
+}
+
+fn operand((mode, value): (ParameterMode, isize)) -> String {
+    match mode {
+        ParameterMode::Immediate => format!("{}", value),
+        ParameterMode::Position => format!("[{}]", value),
+        ParameterMode::Relative if value < 0 => format!("[rb-{}]", -value),
+        ParameterMode::Relative => format!("[rb+{}]", value),
+    }
+}
+
+/// Assembly like text, the destination is the last operand.
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Operation::*;
+        match *self {
+            Add { left_op, right_op, dest_pos } => write!(f, "add {}, {}, {}", operand(left_op), operand(right_op), operand(dest_pos)),
+            Mul { left_op, right_op, dest_pos } => write!(f, "mul {}, {}, {}", operand(left_op), operand(right_op), operand(dest_pos)),
+            LessThan { left_op, right_op, dest_pos } => write!(f, "lt {}, {}, {}", operand(left_op), operand(right_op), operand(dest_pos)),
+            Equals { left_op, right_op, dest_pos } => write!(f, "eq {}, {}, {}", operand(left_op), operand(right_op), operand(dest_pos)),
+            Input { dest_pos } => write!(f, "in {}", operand(dest_pos)),
+            Output { inp_pos } => write!(f, "out {}", operand(inp_pos)),
+            JumpIfTrue { bool_param, jump_dest } => write!(f, "jnz {}, {}", operand(bool_param), operand(jump_dest)),
+            JumpIfFalse { bool_param, jump_dest } => write!(f, "jz {}, {}", operand(bool_param), operand(jump_dest)),
+            SetRelativeOffset { source } => write!(f, "arb {}", operand(source)),
+            Halt => write!(f, "halt"),
+        }
+    }
+}
+
+/// Hooks called by a running `Program`.
+///
+/// All hooks do nothing by default, so an observer only needs to implement the events it is
+/// interested in. A `Program` without observer uses `NoObserver`, for which all calls are
+/// optimized away.
+pub trait Observer {
+    /// An instruction is about to be decoded at `ip`.
+    fn fetch(&mut self, _ip: usize, _instruction: isize) {}
+
+    /// An instruction parameter was read from memory.
+    fn read(&mut self, _addr: usize, _value: isize) {}
+
+    /// `addr` was changed from `old` to `new`. For device mapped addresses `old` is always 0.
+    fn write(&mut self, _addr: usize, _old: isize, _new: isize) {}
+
+    /// An input value was consumed and stored at `addr`.
+    fn input(&mut self, _addr: usize, _value: isize) {}
+
+    fn output(&mut self, _value: isize) {}
+
+    /// A jump instruction at `from` changed the instruction pointer to `to`.
+    fn jump(&mut self, _from: usize, _to: usize) {}
+
+    /// A halt instruction at `ip` was executed.
+    fn halt(&mut self, _ip: usize) {}
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NoObserver;
+
+impl Observer for NoObserver {}
+
+impl<O: Observer + ?Sized> Observer for Box<O> {
+    fn fetch(&mut self, ip: usize, instruction: isize) {
+        (**self).fetch(ip, instruction)
+    }
+
+    fn read(&mut self, addr: usize, value: isize) {
+        (**self).read(addr, value)
+    }
+
+    fn write(&mut self, addr: usize, old: isize, new: isize) {
+        (**self).write(addr, old, new)
+    }
+
+    fn input(&mut self, addr: usize, value: isize) {
+        (**self).input(addr, value)
+    }
+
+    fn output(&mut self, value: isize) {
+        (**self).output(value)
+    }
+
+    fn jump(&mut self, from: usize, to: usize) {
+        (**self).jump(from, to)
+    }
+
+    fn halt(&mut self, ip: usize) {
+        (**self).halt(ip)
+    }
+}
+
+/// An instruction added to a `Program` on top of the built-in ones.
+///
+/// Its parameters follow the opcode and use the same parameter modes as the built-in
+/// instructions, encoded in the digits above the opcode.
+/// Most parameters an added instruction can have, the mode digits of all of them have to fit
+/// into an `isize` next to the opcode.
+pub const MAX_ARITY: usize = 16;
+
+pub trait Instruction {
+    /// Number of parameters following the opcode, at most `MAX_ARITY`.
+    fn arity(&self) -> usize;
+
+    /// A fault crashes the program, see `Program::crash_report`.
+    fn eval(&mut self, context: &mut Context) -> Result<Effect, Fault>;
+}
+
+impl<I: Instruction + ?Sized> Instruction for Rc<RefCell<I>> {
+    fn arity(&self) -> usize {
+        self.borrow().arity()
+    }
+
+    fn eval(&mut self, context: &mut Context) -> Result<Effect, Fault> {
+        self.borrow_mut().eval(context)
+    }
+}
+
+/// What happens after an added instruction was evaluated.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Effect {
+    /// Continue with the next instruction.
+    Continue,
+    Jump(usize),
+    Output(isize),
+    Halt,
+    /// Stop `run` with `ProgramState::Trap`, the next call continues with the next instruction.
+    Trap,
+}
+
+/// Access to the machine for an added instruction.
+pub struct Context<'a> {
+    memory: &'a mut Memory,
+    observer: &'a mut dyn Observer,
+    params: &'a [(ParameterMode, isize)],
+    instruction_ptr: usize,
+    relative_base: isize,
+}
+
+impl Context<'_> {
+    pub fn instruction_ptr(&self) -> usize {
+        self.instruction_ptr
+    }
+
+    pub fn relative_base(&self) -> isize {
+        self.relative_base
+    }
+
+    /// The raw parameter `idx` together with its mode.
+    pub fn param(&self, idx: usize) -> (ParameterMode, isize) {
+        self.params[idx]
+    }
+
+    /// Read the value of parameter `idx` according to its mode.
+    pub fn read(&mut self, idx: usize) -> Result<isize, Fault> {
+        let (mode, value) = self.params[idx];
+        mode.fetch(value, self.relative_base, self.memory, self.observer)
+    }
+
+    /// Write `value` to the address given by parameter `idx`.
+    pub fn write(&mut self, idx: usize, value: isize) -> Result<(), Fault> {
+        let (mode, param) = self.params[idx];
+        let addr = mode.fetch_addr(param, self.relative_base, self.memory)?;
+        store(self.memory, addr, value, self.observer);
+        Ok(())
+    }
+}
+
+struct Mapping {
+    start: usize,
+    end: usize,
+    device: Box<dyn Device>,
+}
+
+/// Number of cells in a memory page.
+const PAGE_SIZE: usize = 4096;
+
+/// Plain memory cells plus the devices mapped over them.
+///
+/// The cells are split into pages which are shared copy-on-write between forked programs. All
+/// pages that were never written to start out as the same shared page of zeros.
+struct Memory {
+    pages: Vec<Rc<[isize]>>,
+    len: usize,
+    /// Sum of `cell_hash` over all cells, kept up to date on every store.
+    hash: u64,
+    devices: Vec<Mapping>,
+    tracker: Option<Box<CodeTracker>>,
+}
+
+/// Finalizer of the splitmix64 generator, spreads every input bit over the whole output.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Contribution of a single cell to the memory hash. Zero cells contribute nothing, so the hash
+/// only depends on the non-zero cells.
+#[inline]
+fn cell_hash(addr: usize, value: isize) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+    mix((addr as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ value as u64)
+}
+
+impl Memory {
+    fn new(cells: &[isize], size: usize) -> Self {
+        let len = size.max(cells.len());
+        let zeros: Rc<[isize]> = vec![0; PAGE_SIZE].into();
+        let pages = (0..len).step_by(PAGE_SIZE)
+            .map(|start| {
+                let end = (start + PAGE_SIZE).min(len);
+                if start >= cells.len() && end - start == PAGE_SIZE {
+                    return zeros.clone();
+                }
+                let mut page = cells.get(start..end.min(cells.len())).unwrap_or_default().to_vec();
+                page.resize(end - start, 0);
+                page.into()
+            })
+            .collect();
+        let hash = cells.iter()
+            .enumerate()
+            .fold(0u64, |hash, (addr, &value)| hash.wrapping_add(cell_hash(addr, value)));
+        Memory { pages, len, hash, devices: Vec::new(), tracker: None }
+    }
+
+    /// Share all pages with a new memory. Devices can't be shared, the new memory has none.
+    fn fork(&self) -> Self {
+        Memory {
+            pages: self.pages.clone(),
+            len: self.len,
+            hash: self.hash,
+            devices: Vec::new(),
+            tracker: self.tracker.clone(),
+        }
+    }
+
+    /// Plain memory content at `addr`, ignoring devices.
+    #[inline]
+    fn cell(&self, addr: usize) -> isize {
+        self.pages[addr / PAGE_SIZE][addr % PAGE_SIZE]
+    }
+
+    /// Plain memory content at `addr`, copying its page first if it is shared.
+    #[inline]
+    fn cell_mut(&mut self, addr: usize) -> &mut isize {
+        let page = &mut self.pages[addr / PAGE_SIZE];
+        if Rc::get_mut(page).is_none() {
+            *page = Rc::from(&page[..]);
+        }
+        &mut Rc::get_mut(page).expect("page was just copied")[addr % PAGE_SIZE]
+    }
+
+    /// Decode the built-in instruction at `addr`.
+    fn decode(&self, addr: usize) -> Result<Operation, DecodeError> {
+        let page = &self.pages[addr / PAGE_SIZE];
+        let offset = addr % PAGE_SIZE;
+        if offset + 4 <= page.len() {
+            return Operation::try_decode(&page[offset..]);
+        }
+        // The instruction may continue on the next page.
+        let mut buffer = [0; 4];
+        let len = (self.len - addr).min(buffer.len());
+        for (idx, cell) in buffer[..len].iter_mut().enumerate() {
+            *cell = self.cell(addr + idx);
+        }
+        Operation::try_decode(&buffer[..len])
+    }
+
+    /// Check that `addr` is in plain memory or a mapped device.
+    fn address(&self, addr: isize) -> Result<usize, Fault> {
+        let valid = addr >= 0 && {
+            let addr = addr as usize;
+            addr < self.len || self.devices.iter().any(|mapping| mapping.start <= addr && addr < mapping.end)
+        };
+        if valid {
+            Ok(addr as usize)
+        } else {
+            Err(Fault::AddressOutOfRange(addr))
+        }
+    }
+
+    fn device_at(&mut self, addr: usize) -> Option<(&mut Mapping, usize)> {
+        self.devices.iter_mut()
+            .find(|mapping| mapping.start <= addr && addr < mapping.end)
+            .map(|mapping| {
+                let offset = addr - mapping.start;
+                (mapping, offset)
+            })
+    }
+
+    #[inline]
+    fn load(&mut self, addr: usize) -> isize {
+        if !self.devices.is_empty() {
+            if let Some((mapping, offset)) = self.device_at(addr) {
+                return mapping.device.read(offset);
+            }
+        }
+        self.cell(addr)
+    }
+
+    /// Store `value` at `addr`, returning the previous content of plain memory.
+    #[inline]
+    fn store(&mut self, addr: usize, value: isize) -> isize {
+        if !self.devices.is_empty() {
+            if let Some((mapping, offset)) = self.device_at(addr) {
+                mapping.device.write(offset, value);
+                return 0;
+            }
+        }
+        let old = std::mem::replace(self.cell_mut(addr), value);
+        self.hash = self.hash.wrapping_sub(cell_hash(addr, old)).wrapping_add(cell_hash(addr, value));
+        if let Some(tracker) = &mut self.tracker {
+            tracker.write(addr, old, value);
+        }
+        old
+    }
+}
+
+/// How a program reacts to self-modifying code.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CodeTracking {
+    Off,
+    /// Record every modification.
+    Record,
+    /// Record every modification and stop `run` with `ProgramState::Trap` on it.
+    Trap,
+}
+
+/// Code and data mixing in a way only self-modifying code does.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Modification {
+    /// The instruction at `writer` overwrote the already executed cell `addr`.
+    Write { writer: usize, addr: usize, old: isize, new: isize },
+    /// The cell `addr` is about to be executed after the instruction at `writer` wrote to it.
+    Execute { writer: usize, addr: usize, old: isize, new: isize },
+}
+
+impl fmt::Display for Modification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Modification::Write { writer, addr, old, new } => {
+                write!(f, "{}: overwrote executed cell {} ({} -> {})", writer, addr, old, new)
+            }
+            Modification::Execute { writer, addr, old, new } => {
+                write!(f, "{}: executing cell written by {} ({} -> {})", addr, writer, old, new)
+            }
+        }
+    }
+}
+
+/// Keeps track of executed and written cells to find self-modifying code.
+#[derive(Debug, Clone)]
+struct CodeTracker {
+    trap: bool,
+    /// The instruction currently being executed.
+    ip: usize,
+    executed: HashSet<usize>,
+    /// Writer and original value of all cells written but not executed since.
+    written: HashMap<usize, (usize, isize)>,
+    modifications: Vec<Modification>,
+    trap_pending: bool,
+}
+
+impl CodeTracker {
+    fn write(&mut self, addr: usize, old: isize, new: isize) {
+        if old == new {
+            return;
+        }
+        if self.executed.contains(&addr) {
+            self.modifications.push(Modification::Write { writer: self.ip, addr, old, new });
+            self.trap_pending |= self.trap;
+        }
+        let ip = self.ip;
+        self.written.entry(addr).and_modify(|(writer, _)| *writer = ip).or_insert((ip, old));
+    }
+
+    /// Mark the `size` cells of the instruction at `ip` as executed. Returns true if this should
+    /// trap.
+    fn execute(&mut self, memory: &Memory, ip: usize, size: usize) -> bool {
+        self.ip = ip;
+        let mut trap = false;
+        for addr in ip..(ip + size).min(memory.len) {
+            if let Some((writer, old)) = self.written.remove(&addr) {
+                let new = memory.cell(addr);
+                if new != old {
+                    self.modifications.push(Modification::Execute { writer, addr, old, new });
+                    trap |= self.trap;
+                }
+            }
+            self.executed.insert(addr);
+        }
+        trap
+    }
+}
+
+pub const DEFAULT_EXTRA_MEMORY: usize = 1_000_000;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ProgramState {
+    AwaitInput,
+    Halt,
+    StepLimit,
+    /// The next instruction is at a breakpoint, see `Program::set_breakpoints`.
+    Breakpoint,
+    /// The same machine state was reached twice without any input in between.
+    InfiniteLoop,
+    /// An added instruction stopped execution.
+    Trap,
+    /// The program can't continue, see `Program::crash_report`.
+    Crash,
+}
+
+/// Brent's cycle detection over the fingerprints of consecutive machine states. Needs constant
+/// memory and finds a cycle at most twice its length plus its start after it was entered.
+#[derive(Debug, Default)]
+struct LoopDetector {
+    saved: Option<u64>,
+    power: usize,
+    length: usize,
+}
+
+impl LoopDetector {
+    fn repeats(&mut self, fingerprint: u64) -> bool {
+        if self.saved == Some(fingerprint) {
+            return true;
+        }
+        self.length += 1;
+        if self.length >= self.power {
+            self.saved = Some(fingerprint);
+            self.power = (self.power * 2).max(1);
+            self.length = 0;
+        }
+        false
+    }
+}
+
+/// Crash reports disassemble at most this many cells before the faulting instruction.
+const CONTEXT_CELLS: usize = 16;
+/// Minimum number of lines disassembled for a crash report.
+const CONTEXT_LINES: usize = 8;
+
+/// Number of executed instructions kept for crash reports by default.
+pub const DEFAULT_CRASH_HISTORY: usize = 16;
+
+/// Everything needed for a crash report besides the current machine state.
+#[derive(Clone)]
+struct CrashLog {
+    /// Memory pages as they were when the program was created.
+    image: Vec<Rc<[isize]>>,
+    /// Ring buffer with the addresses of the most recently executed instructions.
+    history: Vec<usize>,
+    next: usize,
+    /// Number of addresses ever written to `history`.
+    executed: usize,
+    inputs: Vec<isize>,
+    fault: Option<Fault>,
+}
+
+impl CrashLog {
+    fn new(memory: &Memory) -> Self {
+        CrashLog {
+            image: memory.pages.clone(),
+            history: vec![0; DEFAULT_CRASH_HISTORY],
+            next: 0,
+            executed: 0,
+            inputs: Vec::new(),
+            fault: None,
+        }
+    }
+
+    #[inline]
+    fn executed(&mut self, addr: usize) {
+        if let Some(slot) = self.history.get_mut(self.next) {
+            *slot = addr;
+            self.next += 1;
+            if self.next == self.history.len() {
+                self.next = 0;
+            }
+            self.executed += 1;
+        }
+    }
+
+    /// Executed addresses, oldest first.
+    fn history(&self) -> Vec<usize> {
+        if self.executed < self.history.len() {
+            return self.history[..self.executed].to_vec();
+        }
+        self.history[self.next..].iter().chain(&self.history[..self.next]).copied().collect()
+    }
+}
+
+pub struct Program<O: Observer = NoObserver> {
+    memory: Memory,
+    instruction_ptr: usize,
+    relative_offset: isize,
+    steps: usize,
+    step_limit: Option<usize>,
+    breakpoints: HashSet<usize>,
+    /// Stopped at the breakpoint at the instruction pointer, the next run continues past it.
+    at_breakpoint: bool,
+    session: Option<Session>,
+    awaiting_input: bool,
+    loop_detector: Option<LoopDetector>,
+    instructions: HashMap<isize, Box<dyn Instruction>>,
+    crash: CrashLog,
+    observer: O,
+}
+
+impl Program {
+    pub fn new(memory: Vec<isize>) -> Self {
+        let size = memory.len() + DEFAULT_EXTRA_MEMORY;
+        Program::with_memory_size(memory, size)
+    }
+
+    /// Create a program with exactly `size` memory cells, or just the program itself if it is larger.
+    pub fn with_memory_size(memory: Vec<isize>, size: usize) -> Self {
+        let memory = Memory::new(&memory, size);
+        Program {
+            crash: CrashLog::new(&memory),
+            memory,
+            instruction_ptr: 0,
+            relative_offset: 0,
+            steps: 0,
+            step_limit: None,
+            breakpoints: HashSet::new(),
+            at_breakpoint: false,
+            session: None,
+            awaiting_input: false,
+            loop_detector: None,
+            instructions: HashMap::new(),
+            observer: NoObserver,
+        }
+    }
+}
+
+impl<O: Observer> Program<O> {
+    /// Replace the observer of this program, keeping the current machine state.
+    pub fn with_observer<N: Observer>(self, observer: N) -> Program<N> {
+        Program {
+            memory: self.memory,
+            instruction_ptr: self.instruction_ptr,
+            relative_offset: self.relative_offset,
+            steps: self.steps,
+            step_limit: self.step_limit,
+            breakpoints: self.breakpoints,
+            at_breakpoint: self.at_breakpoint,
+            session: self.session,
+            awaiting_input: self.awaiting_input,
+            loop_detector: self.loop_detector,
+            instructions: self.instructions,
+            crash: self.crash,
+            observer,
+        }
+    }
+
+    /// Create an independent copy of this program in its current state.
+    ///
+    /// Memory is shared copy-on-write, only pages written to by either program after the fork
+    /// are copied. Mapped devices and added instructions can't be duplicated, so programs using
+    /// them can't be forked.
+    pub fn fork(&self) -> Result<Self, Error> where O: Clone {
+        if !self.memory.devices.is_empty() {
+            return Err("Can not fork a program with mapped devices".into());
+        }
+        if !self.instructions.is_empty() {
+            return Err("Can not fork a program with added instructions".into());
+        }
+        Ok(Program {
+            memory: self.memory.fork(),
+            instruction_ptr: self.instruction_ptr,
+            relative_offset: self.relative_offset,
+            steps: self.steps,
+            step_limit: self.step_limit,
+            breakpoints: self.breakpoints.clone(),
+            at_breakpoint: self.at_breakpoint,
+            session: self.session.clone(),
+            awaiting_input: self.awaiting_input,
+            loop_detector: self.loop_detector.as_ref().map(|_| LoopDetector::default()),
+            instructions: HashMap::new(),
+            crash: self.crash.clone(),
+            observer: self.observer.clone(),
+        })
+    }
+
+    /// Add `instruction` as `opcode`. The opcodes of the built-in instructions can't be replaced,
+    /// they are always decoded first.
+    pub fn register_instruction(&mut self, opcode: isize, instruction: Box<dyn Instruction>) -> Result<(), Error> {
+        if !(0..100).contains(&opcode) {
+            return Err(format!("Opcode {} is not between 0 and 99", opcode).into());
+        }
+        if Operation::try_decode(&[opcode, 0, 0, 0]).is_ok() {
+            return Err(format!("Opcode {} is a built-in instruction", opcode).into());
+        }
+        if instruction.arity() > MAX_ARITY {
+            return Err(format!("Instruction {} has {} parameters, at most {} are supported", opcode, instruction.arity(), MAX_ARITY).into());
+        }
+        if self.instructions.insert(opcode, instruction).is_some() {
+            return Err(format!("Opcode {} is already registered", opcode).into());
+        }
+        Ok(())
+    }
+
+    /// Let `device` handle all reads and writes to the addresses in `range`.
+    ///
+    /// The range may lie outside of the plain memory, but must not overlap another device.
+    pub fn map_device(&mut self, range: Range<usize>, device: Box<dyn Device>) -> Result<(), Error> {
+        if range.start >= range.end {
+            return Err(format!("Can not map device to empty range {:?}", range).into());
+        }
+        if let Some(other) = self.memory.devices.iter().find(|m| m.start < range.end && range.start < m.end) {
+            return Err(format!("Device range {:?} overlaps with {:?}", range, other.start..other.end).into());
+        }
+        self.memory.devices.push(Mapping { start: range.start, end: range.end, device });
+        Ok(())
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Stop `run` with `ProgramState::StepLimit` once `limit` instructions have been executed in total.
+    pub fn set_step_limit(&mut self, limit: Option<usize>) {
+        self.step_limit = limit;
+    }
+
+    /// Stop `run` with `ProgramState::Breakpoint` before executing an instruction at one of
+    /// `breakpoints`. The next call continues with that instruction.
+    pub fn set_breakpoints(&mut self, breakpoints: HashSet<usize>) {
+        self.breakpoints = breakpoints;
+    }
+
+    pub fn set_instruction_ptr(&mut self, ptr: usize) {
+        self.instruction_ptr = ptr;
+        self.awaiting_input = false;
+        self.at_breakpoint = false;
+    }
+
+    /// Stop `run` with `ProgramState::InfiniteLoop` once a machine state repeats without any
+    /// input in between.
+    ///
+    /// Mapped devices are not part of the machine state, a program that reads from a device
+    /// like `Random` may be reported even though a different read would break the loop.
+    pub fn set_loop_detection(&mut self, enabled: bool) {
+        self.loop_detector = if enabled { Some(LoopDetector::default()) } else { None };
+    }
+
+    /// Track executed and written cells to find self-modifying code: writes to cells that were
+    /// executed before and execution of cells that were written since the program started.
+    /// Writes that don't change a cell are ignored.
+    pub fn set_code_tracking(&mut self, tracking: CodeTracking) {
+        self.memory.tracker = match tracking {
+            CodeTracking::Off => None,
+            _ => Some(Box::new(CodeTracker {
+                trap: tracking == CodeTracking::Trap,
+                ip: self.instruction_ptr,
+                executed: HashSet::new(),
+                written: HashMap::new(),
+                modifications: Vec::new(),
+                trap_pending: false,
+            })),
+        };
+    }
+
+    /// All modifications found since code tracking was enabled.
+    pub fn modifications(&self) -> &[Modification] {
+        self.memory.tracker.as_ref().map_or(&[], |tracker| &tracker.modifications)
+    }
+
+    /// Fingerprint of the full machine state: instruction pointer, relative base and the
+    /// non-zero memory cells. Equal states always have the same fingerprint, different states
+    /// almost never.
+    pub fn fingerprint(&self) -> u64 {
+        let registers = mix(self.instruction_ptr as u64 ^ 0x1).wrapping_add(mix(self.relative_offset as u64 ^ 0x2));
+        mix(self.memory.hash.wrapping_add(registers))
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn instruction_ptr(&self) -> usize {
+        self.instruction_ptr
+    }
+
+    pub fn relative_base(&self) -> isize {
+        self.relative_offset
+    }
+
+    /// Number of plain memory cells, without mapped devices.
+    pub fn memory_size(&self) -> usize {
+        self.memory.len
+    }
+
+    /// Plain memory content at `addr` without triggering devices, `None` if `addr` is outside
+    /// of memory.
+    pub fn peek(&self, addr: usize) -> Option<isize> {
+        if addr < self.memory.len {
+            Some(self.memory.cell(addr))
+        } else {
+            None
+        }
+    }
+
+    /// Keep the addresses of the last `len` executed instructions for crash reports.
+    pub fn set_crash_history(&mut self, len: usize) {
+        self.crash.history = vec![0; len];
+        self.crash.next = 0;
+        self.crash.executed = 0;
+    }
+
+    /// Report on why the last `run` returned `ProgramState::Crash`, `None` if it didn't.
+    ///
+    /// The history only keeps addresses, its instructions are disassembled from memory as it is
+    /// now, which differs from what was executed if the program modified its own code.
+    pub fn crash_report(&self) -> Option<CrashReport> {
+        let fault = self.crash.fault?;
+        let history = self.crash.history();
+        let ip = self.instruction_ptr;
+
+        let mut changes = Vec::new();
+        for (idx, (original, current)) in self.crash.image.iter().zip(&self.memory.pages).enumerate() {
+            if Rc::ptr_eq(original, current) {
+                continue;
+            }
+            for (offset, (&old, &new)) in original.iter().zip(current.iter()).enumerate() {
+                if old != new {
+                    changes.push((idx * PAGE_SIZE + offset, old, new));
+                }
+            }
+        }
+
+        // Disassemble from the start of the last straight line code executed before the fault.
+        let mut start = ip.min(self.memory.len);
+        for &addr in history.iter().rev() {
+            if addr < start && start - addr <= CONTEXT_CELLS {
+                start = addr;
+            }
+        }
+        let mut disassembly = Vec::new();
+        let mut addr = start;
+        while addr < self.memory.len && (addr <= ip || disassembly.len() < CONTEXT_LINES) {
+            let (text, size) = self.disassemble(addr);
+            let size = if addr < ip && addr + size > ip { 0 } else { size };
+            if size == 0 {
+                disassembly.push((addr, format!("data {}", self.memory.cell(addr))));
+                addr += 1;
+            } else {
+                disassembly.push((addr, text));
+                addr += size;
+            }
+        }
+
+        Some(CrashReport {
+            fault,
+            instruction_ptr: ip,
+            relative_base: self.relative_offset,
+            steps: self.steps,
+            history: history.into_iter().map(|addr| (addr, self.disassemble(addr).0)).collect(),
+            inputs: self.crash.inputs.clone(),
+            changes,
+            disassembly,
+        })
+    }
+
+    /// Text and size of the instruction at `addr`, a single data cell if it isn't valid.
+    fn disassemble(&self, addr: usize) -> (String, usize) {
+        if addr >= self.memory.len {
+            return ("outside of memory".to_string(), 1);
+        }
+        match self.memory.decode(addr) {
+            Ok(op) => (op.to_string(), op.size()),
+            Err(DecodeError::UnknownInstruction(opcode)) if self.instructions.contains_key(&opcode) => {
+                let arity = self.instructions[&opcode].arity();
+                let params: Vec<String> = (1..=arity).filter(|idx| addr + idx < self.memory.len).map(|idx| self.memory.cell(addr + idx).to_string()).collect();
+                (format!("op{} {}", opcode, params.join(", ")).trim_end().to_string(), arity + 1)
+            }
+            Err(_) => (format!("data {}", self.memory.cell(addr)), 1),
+        }
+    }
+
+    /// Record all consumed inputs and produced outputs from now on.
+    pub fn start_recording(&mut self) {
+        self.session = Some(Session::default());
+    }
+
+    /// Stop recording, returning the recorded session if recording was started.
+    pub fn stop_recording(&mut self) -> Option<Session> {
+        self.session.take()
+    }
+
+    pub fn run(&mut self, input: &mut Option<isize>) -> (ProgramState, Vec<isize>) {
+        let mut outputs = Vec::new();
+        self.crash.fault = None;
+        while self.instruction_ptr < self.memory.len {
+            // Trap after the instruction that overwrote executed code.
+            if let Some(tracker) = &mut self.memory.tracker {
+                if std::mem::take(&mut tracker.trap_pending) {
+                    return (ProgramState::Trap, outputs);
+                }
+            }
+            if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+                return (ProgramState::StepLimit, outputs);
+            }
+            // An input instruction that had to wait for input was already reported and checked.
+            let resumed = self.awaiting_input;
+            if !resumed && !std::mem::take(&mut self.at_breakpoint) && self.breakpoints.contains(&self.instruction_ptr) {
+                self.at_breakpoint = true;
+                return (ProgramState::Breakpoint, outputs);
+            }
+            if !resumed {
+                if self.memory.tracker.is_some() && self.track_execution() {
+                    return (ProgramState::Trap, outputs);
+                }
+                if self.loop_detector.is_some() {
+                    let fingerprint = self.fingerprint();
+                    if self.loop_detector.as_mut().is_some_and(|detector| detector.repeats(fingerprint)) {
+                        return (ProgramState::InfiniteLoop, outputs);
+                    }
+                }
+                self.observer.fetch(self.instruction_ptr, self.memory.cell(self.instruction_ptr));
+            }
+            self.awaiting_input = false;
+            let op = match self.memory.decode(self.instruction_ptr) {
+                Ok(op) => op,
+                Err(DecodeError::UnknownInstruction(opcode)) if self.instructions.contains_key(&opcode) => {
+                    if let Some(state) = self.eval_instruction(opcode, &mut outputs) {
+                        return (state, outputs);
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    self.crash.fault = Some(Fault::InvalidInstruction(err));
+                    return (ProgramState::Crash, outputs);
+                }
+            };
+            if !resumed {
+                self.crash.executed(self.instruction_ptr);
+            }
+            let op_size = op.size();
+            let result = match op.eval(&mut self.memory, self.relative_offset, &mut self.observer) {
+                Ok(result) => result,
+                Err(fault) => {
+                    self.crash.fault = Some(fault);
+                    return (ProgramState::Crash, outputs);
+                }
+            };
+            match result {
+                EvalResult::Continue => self.instruction_ptr += op_size,
+                EvalResult::SetInstructionPtr(x) => {
+                    self.observer.jump(self.instruction_ptr, x);
+                    self.instruction_ptr = x;
+                }
+                EvalResult::UpdateRelativeOffset(x) => {
+                    self.relative_offset = self.relative_offset.wrapping_add(x);
+                    self.instruction_ptr += op_size;
+                }
+                EvalResult::Halt => {
+                    self.observer.halt(self.instruction_ptr);
+                    return (ProgramState::Halt, outputs);
+                }
+                EvalResult::InputAt(pos) => {
+                    match input.take() {
+                        Some(x) => {
+                            if let Some(session) = &mut self.session {
+                                session.events.push(Event::Input { step: self.steps, value: x });
+                            }
+                            if let Some(detector) = &mut self.loop_detector {
+                                *detector = LoopDetector::default();
+                            }
+                            self.crash.inputs.push(x);
+                            self.observer.input(pos, x);
+                            store(&mut self.memory, pos, x, &mut self.observer);
+                            self.instruction_ptr += op_size;
+                        }
+                        None => {
+                            self.awaiting_input = true;
+                            return (ProgramState::AwaitInput, outputs);
+                        }
+                    }
+                }
+                EvalResult::Output(x) => {
+                    if let Some(session) = &mut self.session {
+                        session.events.push(Event::Output { step: self.steps, value: x });
+                    }
+                    self.observer.output(x);
+                    outputs.push(x);
+                    self.instruction_ptr += op_size
+                }
+            }
+            self.steps += 1;
+        }
+        self.crash.fault = Some(Fault::OutsideMemory);
+        (ProgramState::Crash, outputs)
+    }
+
+    /// Mark the instruction at the instruction pointer as executed. Returns true if this should trap.
+    fn track_execution(&mut self) -> bool {
+        let ip = self.instruction_ptr;
+        let size = match self.memory.decode(ip) {
+            Ok(op) => op.size(),
+            Err(DecodeError::UnknownInstruction(opcode)) => self.instructions.get(&opcode).map_or(1, |i| i.arity() + 1),
+            Err(_) => 1,
+        };
+        let mut tracker = self.memory.tracker.take().expect("only called while tracking");
+        let trap = tracker.execute(&self.memory, ip, size);
+        self.memory.tracker = Some(tracker);
+        trap
+    }
+
+    /// Evaluate the added instruction `opcode` at the instruction pointer. Returns the state to
+    /// stop `run` with, if any.
+    fn eval_instruction(&mut self, opcode: isize, outputs: &mut Vec<isize>) -> Option<ProgramState> {
+        let ip = self.instruction_ptr;
+        let instruction = self.memory.cell(ip);
+        let handler = self.instructions.get_mut(&opcode).expect("only called for registered opcodes");
+        let arity = handler.arity();
+        if ip + arity >= self.memory.len {
+            self.crash.fault = Some(Fault::InvalidInstruction(DecodeError::Truncated));
+            return Some(ProgramState::Crash);
+        }
+        let memory = &self.memory;
+        let params: Result<Vec<(ParameterMode, isize)>, DecodeError> = (0..arity)
+            .map(|idx| {
+                let mode = ParameterMode::try_decode((instruction / 10isize.pow(idx as u32 + 2)) % 10)?;
+                Ok((mode, memory.cell(ip + 1 + idx)))
+            })
+            .collect();
+        let params = match params {
+            Ok(params) => params,
+            Err(err) => {
+                self.crash.fault = Some(Fault::InvalidInstruction(err));
+                return Some(ProgramState::Crash);
+            }
+        };
+        self.crash.executed(ip);
+
+        let mut context = Context {
+            memory: &mut self.memory,
+            observer: &mut self.observer,
+            params: &params,
+            instruction_ptr: ip,
+            relative_base: self.relative_offset,
+        };
+        let effect = match handler.eval(&mut context) {
+            Ok(effect) => effect,
+            Err(fault) => {
+                self.crash.fault = Some(fault);
+                return Some(ProgramState::Crash);
+            }
+        };
+        self.instruction_ptr = ip + 1 + arity;
+        self.steps += 1;
+        match effect {
+            Effect::Continue => None,
+            Effect::Jump(to) => {
+                self.observer.jump(ip, to);
+                self.instruction_ptr = to;
+                None
+            }
+            Effect::Output(x) => {
+                if let Some(session) = &mut self.session {
+                    session.events.push(Event::Output { step: self.steps - 1, value: x });
+                }
+                self.observer.output(x);
+                outputs.push(x);
+                None
+            }
+            Effect::Halt => {
+                self.steps -= 1;
+                self.instruction_ptr = ip;
+                self.observer.halt(ip);
+                Some(ProgramState::Halt)
+            }
+            Effect::Trap => Some(ProgramState::Trap),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default, Clone)]
+    struct Counter {
+        fetches: usize,
+        reads: usize,
+        writes: Vec<(usize, isize, isize)>,
+        inputs: usize,
+        outputs: usize,
+        jumps: Vec<(usize, usize)>,
+        halted_at: Option<usize>,
+    }
+
+    impl Observer for Counter {
+        fn fetch(&mut self, _ip: usize, _instruction: isize) {
+            self.fetches += 1;
+        }
+
+        fn read(&mut self, _addr: usize, _value: isize) {
+            self.reads += 1;
+        }
+
+        fn write(&mut self, addr: usize, old: isize, new: isize) {
+            self.writes.push((addr, old, new));
+        }
+
+        fn input(&mut self, _addr: usize, _value: isize) {
+            self.inputs += 1;
+        }
+
+        fn output(&mut self, _value: isize) {
+            self.outputs += 1;
+        }
+
+        fn jump(&mut self, from: usize, to: usize) {
+            self.jumps.push((from, to));
+        }
+
+        fn halt(&mut self, ip: usize) {
+            self.halted_at = Some(ip);
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        // Read a number, count it down to 0 while printing it.
+        let countdown = vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0];
+        let mut prog = Program::new(countdown).with_observer(Counter::default());
+        assert_eq!(prog.run(&mut None).0, ProgramState::AwaitInput);
+        let (state, outputs) = prog.run(&mut Some(2));
+        assert_eq!(state, ProgramState::Halt);
+        assert_eq!(outputs, vec![2, 1]);
+
+        let counter = prog.observer();
+        assert_eq!(counter.fetches, 8);
+        assert_eq!(counter.reads, 6);
+        assert_eq!(counter.writes, vec![(12, 0, 2), (12, 2, 1), (12, 1, 0)]);
+        assert_eq!(counter.inputs, 1);
+        assert_eq!(counter.outputs, 2);
+        assert_eq!(counter.jumps, vec![(8, 2)]);
+        assert_eq!(counter.halted_at, Some(11));
+    }
+
+    #[test]
+    fn test_relative_base_wraps() {
+        // Moving the relative base past isize::MAX wraps around to 0.
+        let mut prog = Program::new(vec![109, isize::MAX, 109, isize::MAX, 109, 2, 204, 0, 99]);
+        assert_eq!(prog.run(&mut None), (ProgramState::Halt, vec![109]));
+        assert_eq!(prog.relative_base(), 0);
+    }
+
+    #[test]
+    fn test_fork() {
+        // Store the input in a cell far into memory and echo it forever.
+        let echo = vec![3, 10_000, 4, 10_000, 1105, 1, 0];
+        let mut prog = Program::new(echo);
+        prog.run(&mut None);
+        let pages = &prog.memory.pages;
+        assert!(pages[1..pages.len() - 1].iter().all(|page| Rc::ptr_eq(page, &pages[1])));
+
+        let mut fork = prog.fork().unwrap();
+        assert_eq!(fork.run(&mut Some(7)).1, vec![7]);
+        assert_eq!(prog.run(&mut Some(3)).1, vec![3]);
+        assert_eq!(fork.run(&mut Some(8)).1, vec![8]);
+        assert_eq!(fork.steps(), 6);
+        assert_eq!(prog.steps(), 3);
+
+        // Only the written page was copied, the program page is still shared.
+        assert!(Rc::ptr_eq(&prog.memory.pages[0], &fork.memory.pages[0]));
+        assert!(!Rc::ptr_eq(&prog.memory.pages[2], &fork.memory.pages[2]));
+        assert!(Rc::ptr_eq(&prog.memory.pages[3], &fork.memory.pages[3]));
+        assert_eq!((prog.memory.cell(10_000), fork.memory.cell(10_000)), (3, 8));
+
+        prog.map_device(20..21, Box::new(crate::device::Clock::default())).unwrap();
+        assert!(prog.fork().is_err());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let code = vec![1101, 2, 3, 7, 3, 20, 99, 0];
+        let mut prog = Program::new(code.clone());
+        let initial = prog.fingerprint();
+        assert_eq!(initial, Program::new(code.clone()).fingerprint());
+        prog.run(&mut None);
+        assert_ne!(prog.fingerprint(), initial);
+
+        // Writing the same values by different means gives the same state.
+        prog.run(&mut Some(5));
+        let mut expected = code;
+        expected.resize(21, 0);
+        expected[7] = 5;
+        expected[20] = 5;
+        let mut other = Program::new(expected);
+        other.set_instruction_ptr(6);
+        assert_eq!(prog.fingerprint(), other.fingerprint());
+
+        // The incremental hash matches a freshly computed one.
+        let cells: Vec<isize> = (0..30).map(|addr| prog.memory.cell(addr)).collect();
+        assert_eq!(prog.memory.hash, Memory::new(&cells, 0).hash);
+    }
+
+    #[test]
+    fn test_loop_detection() {
+        // Count [20] down from the input, then spin on the last jump forever.
+        let code = vec![3, 20, 1001, 20, -1, 20, 1005, 20, 2, 1105, 1, 9];
+        let mut prog = Program::new(code);
+        prog.set_loop_detection(true);
+        assert_eq!(prog.run(&mut None).0, ProgramState::AwaitInput);
+        assert_eq!(prog.run(&mut None).0, ProgramState::AwaitInput);
+        assert_eq!(prog.run(&mut Some(100)).0, ProgramState::InfiniteLoop);
+        // The loop is entered after 201 instructions and found before twice that.
+        assert!(prog.steps() < 2 * 201);
+
+        // A counter that never repeats is not a loop.
+        let mut prog = Program::new(vec![1001, 20, 1, 20, 1105, 1, 0]);
+        prog.set_loop_detection(true);
+        prog.set_step_limit(Some(10_000));
+        assert_eq!(prog.run(&mut None).0, ProgramState::StepLimit);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // Read a value, then output it three times counting down [20].
+        let code = vec![3, 21, 1101, 0, 3, 20, 4, 21, 1001, 20, -1, 20, 1005, 20, 6, 99];
+        let mut prog = Program::new(code);
+        prog.set_breakpoints(vec![0, 6].into_iter().collect());
+        assert_eq!(prog.run(&mut None), (ProgramState::Breakpoint, vec![]));
+        assert_eq!(prog.instruction_ptr(), 0);
+        // Waiting for input on a breakpoint doesn't stop there again.
+        assert_eq!(prog.run(&mut None), (ProgramState::AwaitInput, vec![]));
+        assert_eq!(prog.run(&mut Some(5)), (ProgramState::Breakpoint, vec![]));
+        assert_eq!(prog.run(&mut None), (ProgramState::Breakpoint, vec![5]));
+        // Running into a step limit doesn't report the same breakpoint again.
+        prog.set_step_limit(Some(prog.steps()));
+        assert_eq!(prog.run(&mut None), (ProgramState::StepLimit, vec![]));
+        prog.set_step_limit(None);
+        assert_eq!(prog.run(&mut None), (ProgramState::Breakpoint, vec![5]));
+        assert_eq!(prog.instruction_ptr(), 6);
+        prog.set_breakpoints(HashSet::new());
+        assert_eq!(prog.run(&mut None), (ProgramState::Halt, vec![5]));
+    }
+
+    struct Accumulate {
+        total: isize,
+    }
+
+    impl Instruction for Accumulate {
+        fn arity(&self) -> usize {
+            2
+        }
+
+        fn eval(&mut self, context: &mut Context) -> Result<Effect, Fault> {
+            self.total += context.read(0)?;
+            context.write(1, self.total)?;
+            Ok(match self.total {
+                x if x > 10 => Effect::Trap,
+                _ => Effect::Continue,
+            })
+        }
+    }
+
+    struct Nop(usize);
+
+    impl Instruction for Nop {
+        fn arity(&self) -> usize {
+            self.0
+        }
+
+        fn eval(&mut self, _: &mut Context) -> Result<Effect, Fault> {
+            Ok(Effect::Continue)
+        }
+    }
+
+    #[test]
+    fn test_instruction() {
+        // Accumulate the input into [20], output it and loop until the accumulator traps.
+        let code = vec![3, 20, 42, 20, 21, 4, 21, 1105, 1, 0];
+        let mut prog = Program::new(code.clone()).with_observer(Counter::default());
+        prog.register_instruction(42, Box::new(Accumulate { total: 0 })).unwrap();
+        assert!(prog.register_instruction(42, Box::new(Accumulate { total: 0 })).is_err());
+        assert!(prog.register_instruction(9, Box::new(Accumulate { total: 0 })).is_err());
+        assert!(prog.register_instruction(100, Box::new(Accumulate { total: 0 })).is_err());
+        assert!(prog.register_instruction(43, Box::new(Nop(MAX_ARITY + 1))).is_err());
+        // The mode of the last parameter is the highest digit.
+        let mut wide = vec![44 + 10isize.pow(MAX_ARITY as u32 + 1)];
+        wide.extend(vec![0; MAX_ARITY]);
+        wide.push(99);
+        let mut wide = Program::new(wide);
+        wide.register_instruction(44, Box::new(Nop(MAX_ARITY))).unwrap();
+        assert_eq!(wide.run(&mut None), (ProgramState::Halt, vec![]));
+
+        assert_eq!(prog.run(&mut None), (ProgramState::AwaitInput, vec![]));
+        assert_eq!(prog.run(&mut Some(4)), (ProgramState::AwaitInput, vec![4]));
+        assert_eq!(prog.run(&mut Some(8)), (ProgramState::Trap, vec![]));
+        assert_eq!(prog.run(&mut None), (ProgramState::AwaitInput, vec![12]));
+        assert_eq!(prog.observer().writes, vec![(20, 0, 4), (21, 0, 4), (20, 4, 8), (21, 4, 12)]);
+        assert!(prog.fork().is_err());
+
+        let mut plain = Program::new(code);
+        plain.run(&mut None);
+        assert_eq!(plain.run(&mut Some(1)), (ProgramState::Crash, vec![]));
+        assert_eq!(plain.crash_report().unwrap().fault, Fault::InvalidInstruction(DecodeError::UnknownInstruction(42)));
+    }
+
+    #[test]
+    fn test_self_modification() {
+        let code = vec![
+            1101, 104, 0, 8, // 0: [8] = 104, turns the halt at 8 into an output
+            1101, 5, 0, 1,   // 4: [1] = 5, overwrites the already executed instruction at 0
+            99, 42,          // 8: output 42 once patched
+            99,
+        ];
+        let mut prog = Program::new(code.clone());
+        prog.set_code_tracking(CodeTracking::Record);
+        assert_eq!(prog.run(&mut None), (ProgramState::Halt, vec![42]));
+        assert_eq!(prog.modifications(), &[
+            Modification::Write { writer: 4, addr: 1, old: 104, new: 5 },
+            Modification::Execute { writer: 0, addr: 8, old: 99, new: 104 },
+        ]);
+        assert_eq!(prog.modifications()[1].to_string(), "8: executing cell written by 0 (99 -> 104)");
+
+        let mut prog = Program::new(code);
+        prog.set_code_tracking(CodeTracking::Trap);
+        assert_eq!(prog.run(&mut None), (ProgramState::Trap, vec![]));
+        assert_eq!(prog.modifications().len(), 1);
+        assert_eq!(prog.run(&mut None), (ProgramState::Trap, vec![]));
+        assert_eq!(prog.modifications().len(), 2);
+        assert_eq!(prog.run(&mut None), (ProgramState::Halt, vec![42]));
+    }
+
+    #[test]
+    fn test_page_boundary() {
+        // An add instruction split across the first two pages.
+        let mut code = vec![1105, 1, PAGE_SIZE as isize - 2];
+        code.resize(PAGE_SIZE - 2, 0);
+        code.extend(&[1101, 20, 22, 0, 4, 0, 99]);
+        let mut prog = Program::with_memory_size(code, PAGE_SIZE + 6);
+        assert_eq!(prog.memory.pages.len(), 2);
+        assert_eq!(prog.run(&mut None), (ProgramState::Halt, vec![42]));
+    }
+}
@@ -0,0 +1,21 @@
+//! Intcode virtual machine and tooling shared by the `intcode` command line runner.
+//!
+//! The extension points `Instruction`, `Device` and `network::Handler` are also implemented for
+//! `Rc<RefCell<_>>`, so the caller can keep a handle to an extension and inspect it after it was
+//! handed over to a program or network.
+
+pub mod blocks;
+pub mod compiler;
+pub mod crash;
+pub mod dap;
+pub mod decompile;
+pub mod device;
+pub mod extension;
+pub mod image;
+pub mod intcode;
+pub mod json;
+pub mod network;
+pub mod session;
+pub mod translate;
+
+pub type Error = Box<dyn std::error::Error + 'static>;
@@ -0,0 +1,533 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs,
+    io::{self, BufRead, Read, Write},
+    rc::Rc,
+};
+
+use intcode::{
+    Error,
+    compiler::compile,
+    dap,
+    decompile::decompile,
+    device::{Clock, Console, Device, Random},
+    image::Image,
+    extension::{DebugPrint, Trap},
+    intcode::{CodeTracking, Instruction, Observer, Program, ProgramState},
+    network::{Nat, Network},
+    session::Session,
+    translate::translate,
+};
+
+const USAGE: &str = "\
+Usage: intcode [OPTIONS] [PROGRAM]
+
+Runs the Intcode program stored in PROGRAM, or read from stdin if PROGRAM is missing or '-'.
+Outputs are printed as soon as the program produces them. The program can be given as comma
+separated text or as binary image.
+
+Options:
+    -i, --input VALUES      Input values: comma separated numbers, or a line of text in ASCII mode.
+                            May be given multiple times.
+    -f, --input-file PATH   Read all input values from PATH.
+    -a, --ascii             Exchange input and output as ASCII text instead of numbers.
+    -m, --memory CELLS      Total number of memory cells (default: program size + 1000000).
+    -s, --steps LIMIT       Abort after executing LIMIT instructions.
+    -c, --convert PATH      Instead of running the program, convert it to PATH. Text is converted
+                            to a binary image and a binary image to text.
+    -l, --compile PATH      Instead of running the program, compile it from the Intcode language and
+                            write the result as text to PATH.
+    -D, --decompile         Instead of running the program, print it as structured pseudo-code.
+    -x, --translate PATH    Instead of running the program, translate it to a Rust module at PATH.
+        --dap               Instead of running a program, serve the debug adapter protocol on stdin
+                            and stdout. The program is named by the launch request.
+    -n, --network COUNT     Boot COUNT copies of the program as a packet network with a NAT at
+                            address 255 and print all traffic until the network stalls.
+    -r, --record PATH       Write all inputs and outputs with their instruction count to PATH.
+        --replay PATH       Run the program with the inputs recorded in PATH and check that it
+                            produces the same outputs.
+        --detect-loops      Abort when the program reaches the same state twice without reading input.
+        --self-modification MODE
+                            Report self-modifying code to stderr: writes to executed cells and
+                            execution of written cells. MODE is 'record' to report all of them
+                            at the end or 'trap' to stop at the first one.
+    -t, --trace             Print every executed instruction and memory access to stderr.
+    -d, --device KIND@ADDR  Map a device to address ADDR. KIND is one of 'clock' (counts up on
+                            every read), 'random' (random number on every read, write to seed) or
+                            'console' (prints written values as ASCII). May be given multiple times.
+    -e, --instruction KIND@OPCODE
+                            Add an instruction with the unused OPCODE. KIND is one of 'print'
+                            (prints its parameter to stderr) or 'trap' (stops the program).
+                            May be given multiple times.
+    -h, --help              Print this message.
+
+Without --input or --input-file, input values are read from stdin whenever the program asks for them.";
+
+/// Address of the NAT in `--network` mode.
+const NAT_ADDRESS: usize = 255;
+
+fn main() -> Result<(), Error> {
+    let options = match Options::parse(std::env::args().skip(1))? {
+        Some(options) => options,
+        None => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+    };
+
+    if options.dap {
+        return dap::serve(io::stdin(), io::stdout());
+    }
+
+    let source = match &options.program {
+        Some(path) => fs::read(path)?,
+        None => {
+            let mut source = Vec::new();
+            io::stdin().read_to_end(&mut source)?;
+            source
+        }
+    };
+
+    if let Some(path) = &options.convert {
+        return convert(&source, path);
+    }
+    if options.decompile {
+        let image = Image::load(&source)?;
+        print!("{}", decompile(&image.memory, image.entry_point));
+        return Ok(());
+    }
+    if let Some(path) = &options.translate {
+        let image = Image::load(&source)?;
+        fs::write(path, translate(&image.memory, image.entry_point)?)?;
+        return Ok(());
+    }
+    if let Some(path) = &options.compile {
+        let program = compile(std::str::from_utf8(&source)?)?;
+        fs::write(path, Image::new(program).to_text()?)?;
+        return Ok(());
+    }
+
+    if let Some(count) = options.network {
+        return run_network(&source, &options, count);
+    }
+
+    if let Some(path) = &options.replay {
+        let session: Session = fs::read_to_string(path)?.parse()?;
+        let mut prog = load_program(&source, options.memory)?;
+        prog.set_step_limit(options.steps);
+        prog.set_loop_detection(options.detect_loops);
+        let events = session.replay(&mut prog)?;
+        eprintln!("Replay matches all {} recorded events", events);
+        return Ok(());
+    }
+
+    let mut inputs: Box<dyn InputSource> = match (&options.input_file, options.inputs.is_empty()) {
+        (Some(path), _) => {
+            let content = fs::read_to_string(path)?;
+            Box::new(parse_inputs(&[content], options.ascii, false)?)
+        }
+        (None, false) => Box::new(parse_inputs(&options.inputs, options.ascii, true)?),
+        (None, true) if options.program.is_none() => return Err("stdin is used for the program, pass inputs with --input or --input-file".into()),
+        (None, true) => Box::new(InteractiveInput { ascii: options.ascii, pending: VecDeque::new() }),
+    };
+
+    let mut prog = load_program(&source, options.memory)?;
+    prog.set_step_limit(options.steps);
+    prog.set_loop_detection(options.detect_loops);
+    if let Some(tracking) = options.code_tracking {
+        prog.set_code_tracking(tracking);
+    }
+    for (kind, addr) in &options.devices {
+        let device: Box<dyn Device> = match kind.as_str() {
+            "clock" => Box::new(Clock::default()),
+            "random" => Box::new(Random::new(0)),
+            _ => Box::new(Console::new(io::stdout())),
+        };
+        prog.map_device(*addr..*addr + 1, device)?;
+    }
+    for (kind, opcode) in &options.instructions {
+        let instruction: Box<dyn Instruction> = match kind.as_str() {
+            "print" => Box::new(DebugPrint::new(io::stderr())),
+            _ => Box::new(Trap),
+        };
+        prog.register_instruction(*opcode, instruction)?;
+    }
+
+    if options.trace {
+        run_program(prog.with_observer(Tracer), &options, inputs.as_mut())
+    } else {
+        run_program(prog, &options, inputs.as_mut())
+    }
+}
+
+fn run_program<O: Observer>(mut prog: Program<O>, options: &Options, inputs: &mut dyn InputSource) -> Result<(), Error> {
+    if options.record.is_some() {
+        prog.start_recording();
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = execute(&mut prog, inputs, options.ascii, &mut out);
+    for modification in prog.modifications() {
+        eprintln!("Self modification at {}", modification);
+    }
+
+    if let (Some(path), Some(session)) = (&options.record, prog.stop_recording()) {
+        fs::write(path, session.to_string())?;
+    }
+    result
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Options {
+    program: Option<String>,
+    inputs: Vec<String>,
+    input_file: Option<String>,
+    ascii: bool,
+    memory: Option<usize>,
+    steps: Option<usize>,
+    convert: Option<String>,
+    compile: Option<String>,
+    decompile: bool,
+    translate: Option<String>,
+    network: Option<usize>,
+    dap: bool,
+    record: Option<String>,
+    replay: Option<String>,
+    trace: bool,
+    detect_loops: bool,
+    code_tracking: Option<CodeTracking>,
+    devices: Vec<(String, usize)>,
+    instructions: Vec<(String, isize)>,
+}
+
+impl Options {
+    /// Parse the command line, returns `None` if only the usage should be printed.
+    fn parse<I: Iterator<Item=String>>(mut args: I) -> Result<Option<Self>, Error> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "-a" | "--ascii" => options.ascii = true,
+                "-t" | "--trace" => options.trace = true,
+                "-D" | "--decompile" => options.decompile = true,
+                "--detect-loops" => options.detect_loops = true,
+                "--dap" => options.dap = true,
+                "--self-modification" => options.code_tracking = match Self::value(&arg, args.next())?.as_str() {
+                    "record" => Some(CodeTracking::Record),
+                    "trap" => Some(CodeTracking::Trap),
+                    x => return Err(format!("Unknown self modification mode '{}'", x).into()),
+                },
+                "-i" | "--input" => options.inputs.push(Self::value(&arg, args.next())?),
+                "-f" | "--input-file" => options.input_file = Some(Self::value(&arg, args.next())?),
+                "-m" | "--memory" => options.memory = Some(Self::value(&arg, args.next())?.parse()?),
+                "-s" | "--steps" => options.steps = Some(Self::value(&arg, args.next())?.parse()?),
+                "-d" | "--device" => options.devices.push(Self::device(&Self::value(&arg, args.next())?)?),
+                "-e" | "--instruction" => options.instructions.push(Self::instruction(&Self::value(&arg, args.next())?)?),
+                "-c" | "--convert" => options.convert = Some(Self::value(&arg, args.next())?),
+                "-l" | "--compile" => options.compile = Some(Self::value(&arg, args.next())?),
+                "-x" | "--translate" => options.translate = Some(Self::value(&arg, args.next())?),
+                "-n" | "--network" => options.network = Some(Self::value(&arg, args.next())?.parse()?),
+                "-r" | "--record" => options.record = Some(Self::value(&arg, args.next())?),
+                "--replay" => options.replay = Some(Self::value(&arg, args.next())?),
+                "-" if options.program.is_none() => (),
+                x if x.starts_with('-') => return Err(format!("Unknown option {}\n\n{}", x, USAGE).into()),
+                _ if options.program.is_none() => options.program = Some(arg),
+                x => return Err(format!("Unexpected argument {}\n\n{}", x, USAGE).into()),
+            }
+        }
+
+        if options.input_file.is_some() && !options.inputs.is_empty() {
+            return Err("--input and --input-file can not be combined".into());
+        }
+        if options.dap && options.program.is_some() {
+            return Err("--dap takes the program from the launch request".into());
+        }
+        if options.replay.is_some() && (options.input_file.is_some() || !options.inputs.is_empty() || options.record.is_some()) {
+            return Err("--replay takes its inputs from the session and can not be combined with other inputs or --record".into());
+        }
+
+        Ok(Some(options))
+    }
+
+    fn device(spec: &str) -> Result<(String, usize), Error> {
+        let mut parts = spec.splitn(2, '@');
+        let kind = parts.next().unwrap_or_default();
+        let addr = parts.next().ok_or_else(|| format!("Device '{}' is missing an address, expected KIND@ADDR", spec))?;
+        match kind {
+            "clock" | "random" | "console" => Ok((kind.to_string(), addr.parse()?)),
+            x => Err(format!("Unknown device kind '{}'", x).into()),
+        }
+    }
+
+    fn instruction(spec: &str) -> Result<(String, isize), Error> {
+        let mut parts = spec.splitn(2, '@');
+        let kind = parts.next().unwrap_or_default();
+        let opcode = parts.next().ok_or_else(|| format!("Instruction '{}' is missing an opcode, expected KIND@OPCODE", spec))?;
+        match kind {
+            "print" | "trap" => Ok((kind.to_string(), opcode.parse()?)),
+            x => Err(format!("Unknown instruction kind '{}'", x).into()),
+        }
+    }
+
+    fn value(option: &str, value: Option<String>) -> Result<String, Error> {
+        value.ok_or_else(|| format!("Missing value for {}", option).into())
+    }
+}
+
+fn run_network(source: &[u8], options: &Options, count: usize) -> Result<(), Error> {
+    let mut programs = Vec::new();
+    for _ in 0..count {
+        let mut prog = load_program(source, options.memory)?;
+        prog.set_step_limit(options.steps);
+        prog.set_loop_detection(options.detect_loops);
+        programs.push(prog);
+    }
+    let mut network = Network::new(programs);
+    let nat = Rc::new(RefCell::new(Nat::new(NAT_ADDRESS)));
+    network.attach(NAT_ADDRESS, Box::new(nat.clone()))?;
+
+    let state = network.run(None)?;
+    for traffic in network.log() {
+        println!("{}", traffic);
+    }
+    eprintln!("Network stopped after {} rounds: {:?}", network.rounds(), state);
+    if let Some(y) = nat.borrow().repeated() {
+        eprintln!("NAT sent Y value {} twice in a row", y);
+    }
+    Ok(())
+}
+
+fn load_program(source: &[u8], memory: Option<usize>) -> Result<Program, Error> {
+    let image = Image::load(source)?;
+    let mut prog = match memory {
+        Some(size) => Program::with_memory_size(image.memory, size),
+        None => Program::new(image.memory),
+    };
+    prog.set_instruction_ptr(image.entry_point);
+    Ok(prog)
+}
+
+fn convert(source: &[u8], path: &str) -> Result<(), Error> {
+    let converted = if Image::is_image(source) {
+        Image::decode(source)?.to_text()?.into_bytes()
+    } else {
+        Image::parse_text(std::str::from_utf8(source)?)?.encode()
+    };
+    fs::write(path, converted)?;
+    Ok(())
+}
+
+/// Turn the given input texts into input values.
+///
+/// In ASCII mode, every text is sent character by character. With `add_newline` each text is
+/// treated as a single line, so a newline is appended.
+fn parse_inputs(texts: &[String], ascii: bool, add_newline: bool) -> Result<VecDeque<isize>, Error> {
+    let mut values = VecDeque::new();
+    for text in texts {
+        if ascii {
+            values.extend(text.bytes().map(isize::from));
+            if add_newline {
+                values.push_back(b'\n' as isize);
+            }
+        } else {
+            for part in text.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()) {
+                values.push_back(part.parse()?);
+            }
+        }
+    }
+    Ok(values)
+}
+
+trait InputSource {
+    /// The next input value, or `None` if there is no more input.
+    fn next_value(&mut self) -> Result<Option<isize>, Error>;
+}
+
+impl InputSource for VecDeque<isize> {
+    fn next_value(&mut self) -> Result<Option<isize>, Error> {
+        Ok(self.pop_front())
+    }
+}
+
+/// Reads a new line from stdin every time the program runs out of input values.
+struct InteractiveInput {
+    ascii: bool,
+    pending: VecDeque<isize>,
+}
+
+impl InputSource for InteractiveInput {
+    fn next_value(&mut self) -> Result<Option<isize>, Error> {
+        while self.pending.is_empty() {
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end_matches(['\n', '\r']);
+            self.pending = parse_inputs(&[line.to_string()], self.ascii, true)?;
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+/// Prints all events of the running program to stderr.
+struct Tracer;
+
+impl Observer for Tracer {
+    fn fetch(&mut self, ip: usize, instruction: isize) {
+        eprintln!("{:>8}: {}", ip, instruction);
+    }
+
+    fn read(&mut self, addr: usize, value: isize) {
+        eprintln!("          read  [{}] = {}", addr, value);
+    }
+
+    fn write(&mut self, addr: usize, old: isize, new: isize) {
+        eprintln!("          write [{}] = {} (was {})", addr, new, old);
+    }
+
+    fn input(&mut self, _addr: usize, value: isize) {
+        eprintln!("          input {}", value);
+    }
+
+    fn output(&mut self, value: isize) {
+        eprintln!("          output {}", value);
+    }
+
+    fn jump(&mut self, _from: usize, to: usize) {
+        eprintln!("          jump to {}", to);
+    }
+
+    fn halt(&mut self, _ip: usize) {
+        eprintln!("          halt");
+    }
+}
+
+fn execute<O: Observer>(prog: &mut Program<O>, inputs: &mut dyn InputSource, ascii: bool, out: &mut dyn Write) -> Result<(), Error> {
+    let mut input = None;
+    loop {
+        let (state, outputs) = prog.run(&mut input);
+        for value in outputs {
+            match value {
+                0..=127 if ascii => write!(out, "{}", value as u8 as char)?,
+                _ => writeln!(out, "{}", value)?,
+            }
+        }
+        out.flush()?;
+
+        match state {
+            ProgramState::Halt => return Ok(()),
+            ProgramState::StepLimit => return Err(format!("Step limit reached after {} instructions", prog.steps()).into()),
+            ProgramState::InfiniteLoop => return Err(format!("Infinite loop detected after {} instructions", prog.steps()).into()),
+            ProgramState::Trap | ProgramState::Breakpoint => return Err(format!("Program trapped after {} instructions", prog.steps()).into()),
+            ProgramState::Crash => {
+                let report = prog.crash_report().expect("crashed program has a report");
+                eprint!("{}", report);
+                return Err(format!("Program crashed: {}", report.fault).into());
+            }
+            ProgramState::AwaitInput => {
+                input = inputs.next_value()?;
+                if input.is_none() {
+                    return Err(format!("Program requires more input after {} instructions", prog.steps()).into());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> impl Iterator<Item=String> + '_ {
+        s.split_whitespace().map(String::from)
+    }
+
+    fn run_with(source: &str, inputs: &[&str], ascii: bool) -> Result<String, Error> {
+        let texts: Vec<String> = inputs.iter().map(|s| s.to_string()).collect();
+        let mut inputs = parse_inputs(&texts, ascii, true)?;
+        let mut prog = load_program(source.as_bytes(), None)?;
+        let mut out = Vec::new();
+        execute(&mut prog, &mut inputs, ascii, &mut out)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = Options::parse(args("-i 1,2 --input 3 -s 100 --memory 50 prog.txt")).unwrap().unwrap();
+        assert_eq!(options, Options {
+            program: Some("prog.txt".to_string()),
+            inputs: vec!["1,2".to_string(), "3".to_string()],
+            input_file: None,
+            ascii: false,
+            memory: Some(50),
+            steps: Some(100),
+            convert: None,
+            compile: None,
+            decompile: false,
+            translate: None,
+            network: None,
+            dap: false,
+            record: None,
+            replay: None,
+            trace: false,
+            detect_loops: false,
+            code_tracking: None,
+            devices: vec![],
+            instructions: vec![],
+        });
+        let options = Options::parse(args("-d clock@100 --device console@101 p")).unwrap().unwrap();
+        assert_eq!(options.devices, vec![("clock".to_string(), 100), ("console".to_string(), 101)]);
+        assert!(Options::parse(args("-d disk@100")).is_err());
+        assert!(Options::parse(args("-d clock")).is_err());
+        assert_eq!(Options::parse(args("-e trap@50 p")).unwrap().unwrap().instructions, vec![("trap".to_string(), 50)]);
+        assert!(Options::parse(args("-e jump@50 p")).is_err());
+        assert_eq!(Options::parse(args("--self-modification trap p")).unwrap().unwrap().code_tracking, Some(CodeTracking::Trap));
+        assert!(Options::parse(args("--self-modification off p")).is_err());
+        assert_eq!(Options::parse(args("--replay s.txt -")).unwrap().unwrap().replay, Some("s.txt".to_string()));
+        assert_eq!(Options::parse(args("-n 50 p")).unwrap().unwrap().network, Some(50));
+        assert!(Options::parse(args("--replay s.txt -i 1")).is_err());
+        assert!(Options::parse(args("--dap")).unwrap().unwrap().dap);
+        assert!(Options::parse(args("--dap p")).is_err());
+        assert!(Options::parse(args("--help")).unwrap().is_none());
+        assert!(Options::parse(args("-i")).is_err());
+        assert!(Options::parse(args("-f a -i 1")).is_err());
+        assert!(Options::parse(args("a b")).is_err());
+    }
+
+    #[test]
+    fn test_numeric() {
+        let echo_sum = "3,11,3,12,1,11,12,13,4,13,99,0,0,0\n";
+        assert_eq!(run_with(echo_sum, &["3, 4"], false).unwrap(), "7\n");
+        assert!(run_with(echo_sum, &["3"], false).is_err());
+    }
+
+    #[test]
+    fn test_image() {
+        let image = Image { entry_point: 2, memory: vec![99, 0, 104, 42, 99] };
+        let mut prog = load_program(&image.encode(), None).unwrap();
+        let mut out = Vec::new();
+        execute(&mut prog, &mut VecDeque::new(), false, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "42\n");
+    }
+
+    #[test]
+    fn test_ascii() {
+        let echo = "3,7,4,7,1105,1,0,0";
+        let mut prog = load_program(echo.as_bytes(), None).unwrap();
+        let mut inputs = parse_inputs(&["hi".to_string(), "1,2".to_string()], true, true).unwrap();
+        let mut out = Vec::new();
+        assert!(execute(&mut prog, &mut inputs, true, &mut out).is_err());
+        assert_eq!(String::from_utf8(out).unwrap(), "hi\n1,2\n");
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let mut prog = load_program(b"1105,1,0", Some(3)).unwrap();
+        prog.set_step_limit(Some(10));
+        let mut out = Vec::new();
+        assert!(execute(&mut prog, &mut VecDeque::new(), false, &mut out).is_err());
+        assert_eq!(prog.steps(), 10);
+    }
+}
@@ -0,0 +1,347 @@
+//! Network of Intcode machines exchanging packets.
+//!
+//! Every machine receives its network address as first input. A machine sends a packet by
+//! outputting the destination address followed by the two values `X` and `Y`. Received packets
+//! are queued per machine and delivered value by value on every input instruction, a machine
+//! with an empty queue reads `-1` instead.
+//!
+//! Addresses outside of the machines can be claimed by a `Handler`, for example a `Nat` which
+//! keeps the network from stalling.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    rc::Rc,
+};
+
+use crate::{
+    Error,
+    intcode::{NoObserver, Observer, Program, ProgramState},
+};
+
+/// Value a machine reads when no packet is waiting for it.
+pub const NO_PACKET: isize = -1;
+
+/// Number of consecutive quiet rounds after which the network counts as idle.
+pub const DEFAULT_IDLE_ROUNDS: usize = 2;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Packet {
+    pub source: usize,
+    pub dest: usize,
+    pub x: isize,
+    pub y: isize,
+}
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {}: ({}, {})", self.source, self.dest, self.x, self.y)
+    }
+}
+
+/// A packet sent in the given round.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Traffic {
+    pub round: usize,
+    pub packet: Packet,
+}
+
+impl fmt::Display for Traffic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>6} {}", self.round, self.packet)
+    }
+}
+
+/// Receives all packets sent to a special address.
+pub trait Handler {
+    fn receive(&mut self, packet: Packet);
+
+    /// Called whenever the network became idle. A returned packet is sent from the handler's
+    /// address, returning `None` stops the network.
+    fn idle(&mut self) -> Option<Packet> {
+        None
+    }
+}
+
+impl<H: Handler + ?Sized> Handler for Rc<RefCell<H>> {
+    fn receive(&mut self, packet: Packet) {
+        self.borrow_mut().receive(packet)
+    }
+
+    fn idle(&mut self) -> Option<Packet> {
+        self.borrow_mut().idle()
+    }
+}
+
+/// Remembers the last packet it received and resends it to address 0 when the network is idle.
+/// Stops the network once it sent the same `Y` value twice in a row.
+#[derive(Debug, Default)]
+pub struct Nat {
+    pub address: usize,
+    pub last: Option<Packet>,
+    /// `Y` values of all packets sent to wake up the network.
+    pub sent: Vec<isize>,
+}
+
+impl Nat {
+    pub fn new(address: usize) -> Self {
+        Nat { address, ..Default::default() }
+    }
+
+    /// The first `Y` value that was sent twice in a row.
+    pub fn repeated(&self) -> Option<isize> {
+        match self.sent[..] {
+            [.., a, b] if a == b => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl Handler for Nat {
+    fn receive(&mut self, packet: Packet) {
+        self.last = Some(packet);
+    }
+
+    fn idle(&mut self) -> Option<Packet> {
+        if self.repeated().is_some() {
+            return None;
+        }
+        let last = self.last?;
+        self.sent.push(last.y);
+        Some(Packet { source: self.address, dest: 0, x: last.x, y: last.y })
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum NetworkState {
+    /// All machines halted.
+    Halt,
+    /// The network is idle and no handler woke it up.
+    Idle,
+    /// The round limit was reached.
+    RoundLimit,
+}
+
+struct Node<O: Observer> {
+    program: Program<O>,
+    queue: VecDeque<isize>,
+    output: Vec<isize>,
+    halted: bool,
+}
+
+pub struct Network<O: Observer = NoObserver> {
+    nodes: Vec<Node<O>>,
+    handlers: BTreeMap<usize, Box<dyn Handler>>,
+    log: Vec<Traffic>,
+    round: usize,
+    quiet_rounds: usize,
+    idle_rounds: usize,
+}
+
+impl<O: Observer> Network<O> {
+    /// Create a network of `programs`, each one receives its index as network address when the
+    /// first round boots the network.
+    pub fn new(programs: Vec<Program<O>>) -> Self {
+        let nodes = programs.into_iter()
+            .map(|program| Node { program, queue: VecDeque::new(), output: Vec::new(), halted: false })
+            .collect();
+        Network {
+            nodes,
+            handlers: BTreeMap::new(),
+            log: Vec::new(),
+            round: 0,
+            quiet_rounds: 0,
+            idle_rounds: DEFAULT_IDLE_ROUNDS,
+        }
+    }
+
+    /// Attach `handler` to `address`, which must not belong to a machine.
+    pub fn attach(&mut self, address: usize, handler: Box<dyn Handler>) -> Result<(), Error> {
+        if address < self.nodes.len() {
+            return Err(format!("Address {} belongs to a machine", address).into());
+        }
+        if self.handlers.insert(address, handler).is_some() {
+            return Err(format!("Address {} already has a handler", address).into());
+        }
+        Ok(())
+    }
+
+    /// Set the number of consecutive rounds without any packets needed to count as idle.
+    pub fn set_idle_rounds(&mut self, rounds: usize) {
+        self.idle_rounds = rounds.max(1);
+    }
+
+    pub fn program(&self, address: usize) -> Option<&Program<O>> {
+        self.nodes.get(address).map(|node| &node.program)
+    }
+
+    /// All packets sent so far, including those sent by handlers.
+    pub fn log(&self) -> &[Traffic] {
+        &self.log
+    }
+
+    /// Number of rounds run so far, including the boot round.
+    pub fn rounds(&self) -> usize {
+        self.round
+    }
+
+    /// Give every machine one input value, in the first round this is the machine's address.
+    /// Returns true if the network is idle afterwards.
+    pub fn round(&mut self) -> Result<bool, Error> {
+        let sent = self.log.len();
+        let mut waiting = self.round == 0;
+        for address in 0..self.nodes.len() {
+            let value = if self.round == 0 {
+                address as isize
+            } else if let Some(value) = self.nodes[address].queue.pop_front() {
+                waiting = true;
+                value
+            } else {
+                NO_PACKET
+            };
+            self.feed(address, value)?;
+        }
+        self.round += 1;
+
+        if waiting || self.log.len() != sent {
+            self.quiet_rounds = 0;
+        } else {
+            self.quiet_rounds += 1;
+        }
+        Ok(self.quiet_rounds >= self.idle_rounds)
+    }
+
+    /// Run rounds until all machines halt, the network stays idle or `max_rounds` were run.
+    /// Handlers are asked to wake up the network whenever it becomes idle.
+    pub fn run(&mut self, max_rounds: Option<usize>) -> Result<NetworkState, Error> {
+        let mut rounds = 0;
+        loop {
+            if self.nodes.iter().all(|node| node.halted) {
+                return Ok(NetworkState::Halt);
+            }
+            if max_rounds.is_some_and(|max| rounds >= max) {
+                return Ok(NetworkState::RoundLimit);
+            }
+            rounds += 1;
+            if !self.round()? {
+                continue;
+            }
+
+            let wakeups: Vec<Packet> = self.handlers.values_mut().filter_map(|handler| handler.idle()).collect();
+            if wakeups.is_empty() {
+                return Ok(NetworkState::Idle);
+            }
+            for packet in wakeups {
+                self.send(packet)?;
+            }
+            self.quiet_rounds = 0;
+        }
+    }
+
+    fn feed(&mut self, address: usize, value: isize) -> Result<(), Error> {
+        let node = &mut self.nodes[address];
+        if node.halted {
+            return Ok(());
+        }
+        let (state, output) = node.program.run(&mut Some(value));
+        node.output.extend(output);
+        match state {
+            ProgramState::AwaitInput => (),
+            ProgramState::Halt => node.halted = true,
+            ProgramState::StepLimit => return Err(format!("Machine {} reached its step limit", address).into()),
+            ProgramState::InfiniteLoop => return Err(format!("Machine {} is stuck in an infinite loop", address).into()),
+            ProgramState::Trap | ProgramState::Breakpoint => return Err(format!("Machine {} trapped", address).into()),
+            ProgramState::Crash => {
+                let report = node.program.crash_report().expect("crashed program has a report");
+                return Err(format!("Machine {} crashed\n{}", address, report).into());
+            }
+        }
+
+        let packets: Vec<Packet> = node.output
+            .chunks_exact(3)
+            .map(|chunk| match chunk[0] {
+                dest if dest < 0 => Err(format!("Packet from {} to invalid address: {}", address, dest)),
+                dest => Ok(Packet { source: address, dest: dest as usize, x: chunk[1], y: chunk[2] }),
+            })
+            .collect::<Result<_, _>>()?;
+        node.output.drain(..packets.len() * 3);
+        for packet in packets {
+            self.send(packet)?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, packet: Packet) -> Result<(), Error> {
+        self.log.push(Traffic { round: self.round, packet });
+        if let Some(node) = self.nodes.get_mut(packet.dest) {
+            node.queue.extend(&[packet.x, packet.y]);
+        } else if let Some(handler) = self.handlers.get_mut(&packet.dest) {
+            handler.receive(packet);
+        } else {
+            return Err(format!("Packet to unknown address: {}", packet).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+
+    const NODE: &str = "
+        fn main() {
+            let addr = input();
+            let x = 0;
+            let y = 0;
+            if addr == 0 {
+                output(1); output(0); output(5);
+            }
+            while 1 {
+                x = input();
+                if x != -1 {
+                    y = input();
+                    if addr == 1 {
+                        output(255); output(x); output(y + 1);
+                    } else if y < 8 {
+                        output(1); output(x); output(y);
+                    }
+                }
+            }
+        }
+    ";
+
+    #[test]
+    fn test_nat() {
+        let code = compile(NODE).unwrap();
+        let programs = vec![Program::new(code.clone()), Program::new(code)];
+        let mut network = Network::new(programs);
+        let nat = Rc::new(RefCell::new(Nat::new(255)));
+        network.attach(255, Box::new(nat.clone())).unwrap();
+        assert!(network.attach(1, Box::new(Nat::new(1))).is_err());
+
+        assert_eq!(network.run(Some(1000)).unwrap(), NetworkState::Idle);
+        assert_eq!(nat.borrow().sent, vec![6, 7, 8, 8]);
+        assert_eq!(nat.borrow().repeated(), Some(8));
+
+        let log: Vec<(usize, usize, isize)> = network.log().iter().map(|t| (t.packet.source, t.packet.dest, t.packet.y)).collect();
+        assert_eq!(log, vec![
+            (0, 1, 5), (1, 255, 6),
+            (255, 0, 6), (0, 1, 6), (1, 255, 7),
+            (255, 0, 7), (0, 1, 7), (1, 255, 8),
+            (255, 0, 8), (255, 0, 8),
+        ]);
+        assert_eq!(network.log()[0].to_string(), "     0 0 -> 1: (0, 5)");
+    }
+
+    #[test]
+    fn test_unknown_address() {
+        let code = compile("fn main() { output(input() + 7); output(1); output(2); }").unwrap();
+        assert!(Network::new(vec![Program::new(code)]).round().is_err());
+
+        let code = compile("fn main() { output(input() - 7); output(1); output(2); }").unwrap();
+        let err = Network::new(vec![Program::new(code)]).round().unwrap_err();
+        assert_eq!(err.to_string(), "Packet from 0 to invalid address: -7");
+    }
+}
@@ -0,0 +1,164 @@
+//! Recording and replaying of the I/O of an Intcode program.
+//!
+//! A session file has one event per line: the number of instructions executed before the event,
+//! `in` or `out` and the value. Empty lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! # intcode session
+//! 0 in 5
+//! 4 out 25
+//! ```
+
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use crate::{
+    Error,
+    intcode::{Observer, Program, ProgramState},
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Event {
+    Input { step: usize, value: isize },
+    Output { step: usize, value: isize },
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Session {
+    pub events: Vec<Event>,
+}
+
+impl Session {
+    pub fn inputs(&self) -> impl Iterator<Item=isize> + '_ {
+        self.events.iter().filter_map(|event| match event {
+            Event::Input { value, .. } => Some(*value),
+            Event::Output { .. } => None,
+        })
+    }
+
+    /// Run `prog` with the recorded inputs and check that it produces the same events.
+    ///
+    /// Returns the number of checked events.
+    pub fn replay<O: Observer>(&self, prog: &mut Program<O>) -> Result<usize, Error> {
+        prog.start_recording();
+        let mut inputs = self.inputs();
+        let mut input = None;
+        loop {
+            let (state, _) = prog.run(&mut input);
+            match state {
+                ProgramState::AwaitInput => {
+                    input = inputs.next();
+                    if input.is_none() {
+                        break;
+                    }
+                }
+                ProgramState::Halt | ProgramState::StepLimit | ProgramState::Breakpoint | ProgramState::InfiniteLoop | ProgramState::Trap | ProgramState::Crash => break,
+            }
+        }
+
+        let actual = prog.stop_recording().unwrap_or_default();
+        for (idx, (expected, actual)) in self.events.iter().zip(actual.events.iter()).enumerate() {
+            if expected != actual {
+                return Err(format!("Replay diverged at event {}: expected {}, got {}", idx, expected, actual).into());
+            }
+        }
+        match actual.events.len().cmp(&self.events.len()) {
+            std::cmp::Ordering::Less => Err(format!("Replay ended after {} of {} events", actual.events.len(), self.events.len()).into()),
+            std::cmp::Ordering::Greater => Err(format!("Replay produced unrecorded event {}", actual.events[self.events.len()]).into()),
+            std::cmp::Ordering::Equal => Ok(self.events.len()),
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Input { step, value } => write!(f, "{} in {}", step, value),
+            Event::Output { step, value } => write!(f, "{} out {}", step, value),
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        if parts.len() != 3 {
+            return Err(format!("Invalid session event '{}'", s).into());
+        }
+        let step = parts[0].parse()?;
+        let value = parts[2].parse()?;
+        match parts[1] {
+            "in" => Ok(Event::Input { step, value }),
+            "out" => Ok(Event::Output { step, value }),
+            x => Err(format!("Unknown session event kind '{}'", x).into()),
+        }
+    }
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# intcode session")?;
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Session {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let events: Result<Vec<Event>, _> = s.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Event::from_str)
+            .collect();
+        Ok(Session { events: events? })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECHO_SUM: &[isize] = &[3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99, 0, 0, 0];
+
+    fn record(inputs: &[isize]) -> Session {
+        let mut prog = Program::new(ECHO_SUM.to_vec());
+        prog.start_recording();
+        for &i in inputs {
+            prog.run(&mut Some(i));
+        }
+        prog.stop_recording().unwrap()
+    }
+
+    #[test]
+    fn test_record() {
+        let session = record(&[3, 4]);
+        assert_eq!(session.events, vec![
+            Event::Input { step: 0, value: 3 },
+            Event::Input { step: 1, value: 4 },
+            Event::Output { step: 3, value: 7 },
+        ]);
+        assert_eq!(session.to_string().parse::<Session>().unwrap(), session);
+    }
+
+    #[test]
+    fn test_replay() {
+        let session = record(&[3, 4]);
+        assert_eq!(session.replay(&mut Program::new(ECHO_SUM.to_vec())).unwrap(), 3);
+
+        let mut tampered = session.clone();
+        tampered.events[2] = Event::Output { step: 3, value: 8 };
+        assert!(tampered.replay(&mut Program::new(ECHO_SUM.to_vec())).is_err());
+
+        let mut changed = ECHO_SUM.to_vec();
+        changed[4] = 2;
+        assert!(session.replay(&mut Program::new(changed)).is_err());
+    }
+}
@@ -0,0 +1,440 @@
+//! Ahead-of-time translation of Intcode images to Rust.
+//!
+//! The generated module contains a `Machine` with the same interface as `Program::run`. Every
+//! statically recovered basic block becomes one Rust function, jumps to static targets are
+//! constants the dispatcher loop matches on. Indirect jumps return the computed address to the
+//! dispatcher instead.
+//!
+//! Addresses without a translated block run through a small interpreter. This covers targets
+//! of indirect jumps that weren't found statically, as well as self-modifying code: writing to
+//! an address covered by a translated block disables that block, and if the block is the one
+//! currently running, execution leaves it right after the write.
+//!
+//! Faults the interpreter reports as a crash, like accesses outside of memory or writes in
+//! immediate mode, make the machine stop in `State::Crash` at the faulting instruction.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+};
+
+use crate::{
+    Error,
+    blocks::{BasicBlock, find_blocks, flow, Flow},
+    intcode::{Operation, ParameterMode, DEFAULT_EXTRA_MEMORY},
+};
+
+/// Generate the Rust module for `memory`, starting execution at `entry`.
+pub fn translate(memory: &[isize], entry: usize) -> Result<String, Error> {
+    let blocks = compiled_blocks(memory, entry);
+
+    let mut code_end = 0;
+    let mut block_of = vec![0; memory.len()];
+    for (idx, block) in blocks.iter().enumerate() {
+        code_end = code_end.max(block.end);
+        for b in &mut block_of[block.start..block.end] {
+            *b = idx + 1;
+        }
+    }
+    block_of.truncate(code_end);
+    let layout = Layout { block_of, memory_size: memory.len() + DEFAULT_EXTRA_MEMORY };
+
+    let mut out = String::new();
+    writeln!(out, "{}", HEADER)?;
+    writeln!(out, "pub const ENTRY: usize = {};", entry)?;
+    writeln!(out)?;
+    writeln!(out, "pub const IMAGE: &[isize] = &{:?};", memory)?;
+    writeln!(out)?;
+    writeln!(out, "/// Index + 1 of the block covering every address, 0 for addresses outside of blocks.")?;
+    writeln!(out, "const BLOCK_OF: &[u32] = &{:?};", layout.block_of)?;
+    writeln!(out)?;
+    writeln!(out, "const BLOCK_COUNT: usize = {};", blocks.len())?;
+    writeln!(out)?;
+    writeln!(out, "const MEMORY_SIZE: usize = {};", layout.memory_size)?;
+    writeln!(out, "{}", MACHINE)?;
+
+    writeln!(out, "    fn dispatch(&mut self, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {{")?;
+    writeln!(out, "        match self.ip {{")?;
+    for (idx, block) in blocks.iter().enumerate() {
+        writeln!(out, "            {} if !self.invalid[{}] => block_{}(self, input, output),", block.start, idx, block.start)?;
+    }
+    writeln!(out, "            _ => self.step(input, output),")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+
+    for block in &blocks {
+        writeln!(out)?;
+        layout.write_block(&mut out, block)?;
+    }
+    Ok(out)
+}
+
+/// All blocks that are translated. Speculatively decoded blocks can overlap with others, only
+/// the first of overlapping blocks is translated, the others are left to the interpreter.
+fn compiled_blocks(memory: &[isize], entry: usize) -> Vec<BasicBlock> {
+    let all: BTreeMap<usize, BasicBlock> = find_blocks(memory, entry);
+    let mut claimed = vec![false; memory.len()];
+    let mut blocks = Vec::new();
+    for block in all.into_values().filter(|b| !b.instructions.is_empty()) {
+        if claimed[block.start..block.end].iter().any(|&c| c) {
+            continue;
+        }
+        for c in &mut claimed[block.start..block.end] {
+            *c = true;
+        }
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Where the translated blocks are, and how large the generated machine's memory is.
+struct Layout {
+    /// Index + 1 of the block covering every address up to the end of the code, 0 for none.
+    block_of: Vec<usize>,
+    memory_size: usize,
+}
+
+impl Layout {
+    /// Rust expression for the memory index an operand points to. Addresses that aren't known
+    /// to be inside of memory are checked at runtime, leaving the block with a crash at `ip`.
+    fn index(&self, (mode, value): (ParameterMode, isize), ip: usize) -> String {
+        match mode {
+            ParameterMode::Position if value >= 0 && (value as usize) < self.memory_size => format!("{}", value),
+            ParameterMode::Relative => format!("index!(m.relative_base.wrapping_add({}), {})", value, ip),
+            _ => format!("index!({}, {})", value, ip),
+        }
+    }
+
+    fn read(&self, (mode, value): (ParameterMode, isize), ip: usize) -> String {
+        match mode {
+            ParameterMode::Immediate => format!("{}", value),
+            _ => format!("m.memory[{}]", self.index((mode, value), ip)),
+        }
+    }
+
+    /// Store `expr` at the memory index `addr` that `dest_pos` points to.
+    fn write(&self, out: &mut String, dest_pos: (ParameterMode, isize), addr: &str, expr: &str, next: usize) -> Result<(), Error> {
+        let outside_code = match dest_pos {
+            (ParameterMode::Position, value) if value >= 0 => self.block_of.get(value as usize).is_none_or(|&b| b == 0),
+            _ => false,
+        };
+        if outside_code {
+            writeln!(out, "    m.memory[{}] = {};", addr, expr)?;
+        } else {
+            writeln!(out, "    if m.write({}, {}) {{ return Exit::Jump({}); }}", addr, expr, next)?;
+        }
+        Ok(())
+    }
+
+    fn write_block(&self, out: &mut String, block: &BasicBlock) -> Result<(), Error> {
+        writeln!(out, "fn block_{}(m: &mut Machine, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {{", block.start)?;
+        for &(ip, op) in &block.instructions {
+            let next = ip + op.size();
+            let read = |operand| self.read(operand, ip);
+            writeln!(out, "    // {}: {:?}", ip, op)?;
+            let (dest_pos, expr) = match op {
+                Operation::Add { left_op, right_op, dest_pos } => (dest_pos, format!("{} + {}", read(left_op), read(right_op))),
+                Operation::Mul { left_op, right_op, dest_pos } => (dest_pos, format!("{} * {}", read(left_op), read(right_op))),
+                Operation::LessThan { left_op, right_op, dest_pos } => {
+                    (dest_pos, format!("({} < {}) as isize", read(left_op), read(right_op)))
+                }
+                Operation::Equals { left_op, right_op, dest_pos } => {
+                    (dest_pos, format!("({} == {}) as isize", read(left_op), read(right_op)))
+                }
+                Operation::Input { dest_pos } => (dest_pos, "value".to_string()),
+                Operation::Output { inp_pos } => {
+                    writeln!(out, "    output.push({});", read(inp_pos))?;
+                    continue;
+                }
+                Operation::SetRelativeOffset { source } => {
+                    writeln!(out, "    m.relative_base = m.relative_base.wrapping_add({});", read(source))?;
+                    continue;
+                }
+                Operation::Halt => {
+                    writeln!(out, "    return Exit::Halt({});", ip)?;
+                    continue;
+                }
+                Operation::JumpIfTrue { bool_param, jump_dest } | Operation::JumpIfFalse { bool_param, jump_dest } => {
+                    let target = match flow(&op) {
+                        Flow::Jump { target: Some(target), .. } => format!("{}", target),
+                        _ => format!("{} as usize", read(jump_dest)),
+                    };
+                    let cmp = if let Operation::JumpIfTrue { .. } = op { "!=" } else { "==" };
+                    match flow(&op) {
+                        Flow::Jump { conditional: true, .. } => {
+                            writeln!(out, "    if {} {} 0 {{ return Exit::Jump({}); }}", read(bool_param), cmp, target)?;
+                        }
+                        Flow::Jump { conditional: false, .. } => writeln!(out, "    return Exit::Jump({});", target)?,
+                        Flow::Next | Flow::Halt => (),
+                    }
+                    continue;
+                }
+            };
+            if dest_pos.0 == ParameterMode::Immediate {
+                writeln!(out, "    return Exit::Crash({});", ip)?;
+                continue;
+            }
+            let mut addr = self.index(dest_pos, ip);
+            if let Operation::Input { .. } = op {
+                // The destination is checked before any input is consumed, like the interpreter does.
+                if addr.starts_with("index!") {
+                    writeln!(out, "    let addr = {};", addr)?;
+                    addr = "addr".to_string();
+                }
+                writeln!(out, "    let value = match input.take() {{ Some(v) => v, None => return Exit::AwaitInput({}) }};", ip)?;
+            }
+            self.write(out, dest_pos, &addr, &expr, next)?;
+        }
+        writeln!(out, "    Exit::Jump({})", block.end)?;
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+}
+
+const HEADER: &str = "\
+//! Generated by `intcode --translate`, do not edit.
+
+#![allow(clippy::all, unreachable_code, unused_variables, dead_code)]
+";
+
+const MACHINE: &str = r#"
+#[derive(Debug, Eq, PartialEq)]
+pub enum State {
+    AwaitInput,
+    Halt,
+    Crash,
+}
+
+enum Exit {
+    Jump(usize),
+    AwaitInput(usize),
+    Halt(usize),
+    Crash(usize),
+}
+
+/// `addr` as an index into memory, `None` if it is outside of memory.
+fn index(addr: isize) -> Option<usize> {
+    if addr >= 0 && (addr as usize) < MEMORY_SIZE {
+        Some(addr as usize)
+    } else {
+        None
+    }
+}
+
+/// `addr` as an index into memory, leaving the block with a crash at `ip` if it is outside.
+macro_rules! index {
+    ($addr:expr, $ip:expr) => {
+        match index($addr) {
+            Some(addr) => addr,
+            None => return Exit::Crash($ip),
+        }
+    };
+}
+
+pub struct Machine {
+    pub memory: Vec<isize>,
+    pub ip: usize,
+    pub relative_base: isize,
+    invalid: Vec<bool>,
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        let mut memory = IMAGE.to_vec();
+        memory.resize(MEMORY_SIZE, 0);
+        Machine { memory, ip: ENTRY, relative_base: 0, invalid: vec![false; BLOCK_COUNT] }
+    }
+
+    pub fn run(&mut self, input: &mut Option<isize>) -> (State, Vec<isize>) {
+        let mut output = Vec::new();
+        loop {
+            match self.dispatch(input, &mut output) {
+                Exit::Jump(ip) => self.ip = ip,
+                Exit::AwaitInput(ip) => {
+                    self.ip = ip;
+                    return (State::AwaitInput, output);
+                }
+                Exit::Halt(ip) => {
+                    self.ip = ip;
+                    return (State::Halt, output);
+                }
+                Exit::Crash(ip) => {
+                    self.ip = ip;
+                    return (State::Crash, output);
+                }
+            }
+        }
+    }
+
+    /// Write to memory, returns true if this disabled a translated block.
+    fn write(&mut self, addr: usize, value: isize) -> bool {
+        self.memory[addr] = value;
+        match BLOCK_OF.get(addr) {
+            Some(&block) if block != 0 && !self.invalid[block as usize - 1] => {
+                self.invalid[block as usize - 1] = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn param(&self, idx: usize, mode: isize) -> Option<isize> {
+        let value = *self.memory.get(self.ip + idx)?;
+        match mode {
+            0 => Some(self.memory[index(value)?]),
+            1 => Some(value),
+            2 => Some(self.memory[index(self.relative_base.wrapping_add(value))?]),
+            _ => None,
+        }
+    }
+
+    fn addr(&self, idx: usize, mode: isize) -> Option<usize> {
+        let value = *self.memory.get(self.ip + idx)?;
+        match mode {
+            0 => index(value),
+            2 => index(self.relative_base.wrapping_add(value)),
+            _ => None,
+        }
+    }
+
+    /// Interpret the single instruction at `ip`.
+    fn step(&mut self, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {
+        let ip = self.ip;
+        self.try_step(input, output).unwrap_or(Exit::Crash(ip))
+    }
+
+    /// Like `step`, `None` if the instruction faults.
+    fn try_step(&mut self, input: &mut Option<isize>, output: &mut Vec<isize>) -> Option<Exit> {
+        let ip = self.ip;
+        let instruction = *self.memory.get(ip)?;
+        let mode = |idx: u32| (instruction / 10isize.pow(idx + 1)) % 10;
+        Some(match instruction % 100 {
+            op @ 1 | op @ 2 | op @ 7 | op @ 8 => {
+                let dest = self.addr(3, mode(3))?;
+                let (a, b) = (self.param(1, mode(1))?, self.param(2, mode(2))?);
+                let value = match op {
+                    1 => a + b,
+                    2 => a * b,
+                    7 => (a < b) as isize,
+                    _ => (a == b) as isize,
+                };
+                self.write(dest, value);
+                Exit::Jump(ip + 4)
+            }
+            3 => {
+                let dest = self.addr(1, mode(1))?;
+                match input.take() {
+                    Some(value) => {
+                        self.write(dest, value);
+                        Exit::Jump(ip + 2)
+                    }
+                    None => Exit::AwaitInput(ip),
+                }
+            }
+            4 => {
+                output.push(self.param(1, mode(1))?);
+                Exit::Jump(ip + 2)
+            }
+            op @ 5 | op @ 6 => {
+                // The target is only read if the jump is taken, but has to decode either way.
+                if mode(2) > 2 || ip + 2 >= self.memory.len() {
+                    return None;
+                }
+                if (self.param(1, mode(1))? != 0) == (op == 5) {
+                    Exit::Jump(self.param(2, mode(2))? as usize)
+                } else {
+                    Exit::Jump(ip + 3)
+                }
+            }
+            9 => {
+                self.relative_base = self.relative_base.wrapping_add(self.param(1, mode(1))?);
+                Exit::Jump(ip + 2)
+            }
+            99 => Exit::Halt(ip),
+            _ => return None,
+        })
+    }
+"#;
+
+/// `FIXTURE_PROGRAM` translated by `intcode --translate`, compiled to check the generated code.
+#[cfg(test)]
+#[path = "translate_fixture.rs"]
+mod fixture;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Program, ProgramState};
+
+    /// Sums up its inputs until it reads 0, then patches its final halt into an output of 7.
+    /// A negative input makes it read outside of memory and crash.
+    const FIXTURE_PROGRAM: &[isize] = &[
+        109, 30,            // 0: arb 30
+        3, 40,              // 2: in [40]
+        1006, 40, 25,       // 4: jz [40], 25
+        1007, 40, 0, 42,    // 7: lt [40], 0, [42]
+        1005, 42, 23,       // 11: jnz [42], 23
+        1, 40, 41, 41,      // 14: add [40], [41], [41]
+        204, 11,            // 18: out [rb+11]
+        1105, 1, 2,         // 20: jnz 1, 2
+        204, -100,          // 23: out [rb-100]
+        1101, 104, 0, 29,   // 25: add 104, 0, [29]
+        99, 7,              // 29: halt, becomes out 7
+        99,                 // 31: halt
+    ];
+
+    #[test]
+    fn test_translate() {
+        let prog = [
+            3, 20,           // 0: input
+            1005, 20, 10,    // 2: if [20] goto 10
+            104, 0,          // 5: output 0
+            1105, 1, 14,     // 7: goto 14
+            1101, 1, 1, 3,   // 10: [3] = 2, overwrites the jump at 2
+            2106, 0, 21,     // 14: goto [rb + 21]
+            99,
+        ];
+        let code = translate(&prog, 0).unwrap();
+        assert!(code.contains("pub const ENTRY: usize = 0;"));
+        assert!(code.contains("0 if !self.invalid[0] => block_0(self, input, output),"));
+        assert!(code.contains("fn block_10(m: &mut Machine"));
+        assert!(code.contains("    let value = match input.take() { Some(v) => v, None => return Exit::AwaitInput(0) };"));
+        assert!(code.contains("    m.memory[20] = value;"));
+        assert!(code.contains("    if m.memory[20] != 0 { return Exit::Jump(10); }"));
+        assert!(code.contains("    return Exit::Jump(14);"));
+        assert!(code.contains("    if m.write(3, 1 + 1) { return Exit::Jump(14); }"));
+        assert!(code.contains("    return Exit::Jump(m.memory[index!(m.relative_base.wrapping_add(21), 14)] as usize);"));
+        assert!(code.contains("const BLOCK_OF: &[u32] = &[1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 5];"));
+
+        // Writes in immediate mode and to negative addresses crash at the faulting instruction.
+        let code = translate(&[11101, 1, 1, 0, 1101, 1, 1, -5, 99], 0).unwrap();
+        assert!(code.contains("    return Exit::Crash(0);"));
+        assert!(code.contains("    if m.write(index!(-5, 4), 1 + 1) { return Exit::Jump(8); }"));
+    }
+    #[test]
+    fn test_translated_run() {
+        // Regenerate with `intcode --translate src/translate_fixture.rs` on FIXTURE_PROGRAM.
+        assert_eq!(translate(FIXTURE_PROGRAM, 0).unwrap(), include_str!("translate_fixture.rs"));
+
+        let halting = [None, Some(3), Some(4), Some(0)];
+        let crashing = [None, Some(3), Some(-1)];
+        for (inputs, last) in [(&halting[..], ProgramState::Halt), (&crashing[..], ProgramState::Crash)] {
+            let mut machine = fixture::Machine::new();
+            let mut prog = Program::new(FIXTURE_PROGRAM.to_vec());
+            let mut state = ProgramState::AwaitInput;
+            for &input in inputs {
+                let (machine_state, output) = machine.run(&mut { input });
+                let (prog_state, prog_output) = prog.run(&mut { input });
+                state = match machine_state {
+                    fixture::State::AwaitInput => ProgramState::AwaitInput,
+                    fixture::State::Halt => ProgramState::Halt,
+                    fixture::State::Crash => ProgramState::Crash,
+                };
+                assert_eq!((&state, output), (&prog_state, prog_output), "after input {:?}", input);
+            }
+            assert_eq!(state, last);
+            assert_eq!(machine.ip, prog.instruction_ptr());
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Generated by `intcode --translate`, do not edit.
+
+#![allow(clippy::all, unreachable_code, unused_variables, dead_code)]
+
+pub const ENTRY: usize = 0;
+
+pub const IMAGE: &[isize] = &[109, 30, 3, 40, 1006, 40, 25, 1007, 40, 0, 42, 1005, 42, 23, 1, 40, 41, 41, 204, 11, 1105, 1, 2, 204, -100, 1101, 104, 0, 29, 99, 7, 99];
+
+/// Index + 1 of the block covering every address, 0 for addresses outside of blocks.
+const BLOCK_OF: &[u32] = &[1, 1, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4, 4, 5, 5, 6, 6, 6, 6, 6];
+
+const BLOCK_COUNT: usize = 6;
+
+const MEMORY_SIZE: usize = 1000032;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum State {
+    AwaitInput,
+    Halt,
+    Crash,
+}
+
+enum Exit {
+    Jump(usize),
+    AwaitInput(usize),
+    Halt(usize),
+    Crash(usize),
+}
+
+/// `addr` as an index into memory, `None` if it is outside of memory.
+fn index(addr: isize) -> Option<usize> {
+    if addr >= 0 && (addr as usize) < MEMORY_SIZE {
+        Some(addr as usize)
+    } else {
+        None
+    }
+}
+
+/// `addr` as an index into memory, leaving the block with a crash at `ip` if it is outside.
+macro_rules! index {
+    ($addr:expr, $ip:expr) => {
+        match index($addr) {
+            Some(addr) => addr,
+            None => return Exit::Crash($ip),
+        }
+    };
+}
+
+pub struct Machine {
+    pub memory: Vec<isize>,
+    pub ip: usize,
+    pub relative_base: isize,
+    invalid: Vec<bool>,
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        let mut memory = IMAGE.to_vec();
+        memory.resize(MEMORY_SIZE, 0);
+        Machine { memory, ip: ENTRY, relative_base: 0, invalid: vec![false; BLOCK_COUNT] }
+    }
+
+    pub fn run(&mut self, input: &mut Option<isize>) -> (State, Vec<isize>) {
+        let mut output = Vec::new();
+        loop {
+            match self.dispatch(input, &mut output) {
+                Exit::Jump(ip) => self.ip = ip,
+                Exit::AwaitInput(ip) => {
+                    self.ip = ip;
+                    return (State::AwaitInput, output);
+                }
+                Exit::Halt(ip) => {
+                    self.ip = ip;
+                    return (State::Halt, output);
+                }
+                Exit::Crash(ip) => {
+                    self.ip = ip;
+                    return (State::Crash, output);
+                }
+            }
+        }
+    }
+
+    /// Write to memory, returns true if this disabled a translated block.
+    fn write(&mut self, addr: usize, value: isize) -> bool {
+        self.memory[addr] = value;
+        match BLOCK_OF.get(addr) {
+            Some(&block) if block != 0 && !self.invalid[block as usize - 1] => {
+                self.invalid[block as usize - 1] = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn param(&self, idx: usize, mode: isize) -> Option<isize> {
+        let value = *self.memory.get(self.ip + idx)?;
+        match mode {
+            0 => Some(self.memory[index(value)?]),
+            1 => Some(value),
+            2 => Some(self.memory[index(self.relative_base.wrapping_add(value))?]),
+            _ => None,
+        }
+    }
+
+    fn addr(&self, idx: usize, mode: isize) -> Option<usize> {
+        let value = *self.memory.get(self.ip + idx)?;
+        match mode {
+            0 => index(value),
+            2 => index(self.relative_base.wrapping_add(value)),
+            _ => None,
+        }
+    }
+
+    /// Interpret the single instruction at `ip`.
+    fn step(&mut self, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {
+        let ip = self.ip;
+        self.try_step(input, output).unwrap_or(Exit::Crash(ip))
+    }
+
+    /// Like `step`, `None` if the instruction faults.
+    fn try_step(&mut self, input: &mut Option<isize>, output: &mut Vec<isize>) -> Option<Exit> {
+        let ip = self.ip;
+        let instruction = *self.memory.get(ip)?;
+        let mode = |idx: u32| (instruction / 10isize.pow(idx + 1)) % 10;
+        Some(match instruction % 100 {
+            op @ 1 | op @ 2 | op @ 7 | op @ 8 => {
+                let dest = self.addr(3, mode(3))?;
+                let (a, b) = (self.param(1, mode(1))?, self.param(2, mode(2))?);
+                let value = match op {
+                    1 => a + b,
+                    2 => a * b,
+                    7 => (a < b) as isize,
+                    _ => (a == b) as isize,
+                };
+                self.write(dest, value);
+                Exit::Jump(ip + 4)
+            }
+            3 => {
+                let dest = self.addr(1, mode(1))?;
+                match input.take() {
+                    Some(value) => {
+                        self.write(dest, value);
+                        Exit::Jump(ip + 2)
+                    }
+                    None => Exit::AwaitInput(ip),
+                }
+            }
+            4 => {
+                output.push(self.param(1, mode(1))?);
+                Exit::Jump(ip + 2)
+            }
+            op @ 5 | op @ 6 => {
+                // The target is only read if the jump is taken, but has to decode either way.
+                if mode(2) > 2 || ip + 2 >= self.memory.len() {
+                    return None;
+                }
+                if (self.param(1, mode(1))? != 0) == (op == 5) {
+                    Exit::Jump(self.param(2, mode(2))? as usize)
+                } else {
+                    Exit::Jump(ip + 3)
+                }
+            }
+            9 => {
+                self.relative_base = self.relative_base.wrapping_add(self.param(1, mode(1))?);
+                Exit::Jump(ip + 2)
+            }
+            99 => Exit::Halt(ip),
+            _ => return None,
+        })
+    }
+
+    fn dispatch(&mut self, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {
+        match self.ip {
+            0 if !self.invalid[0] => block_0(self, input, output),
+            2 if !self.invalid[1] => block_2(self, input, output),
+            7 if !self.invalid[2] => block_7(self, input, output),
+            14 if !self.invalid[3] => block_14(self, input, output),
+            23 if !self.invalid[4] => block_23(self, input, output),
+            25 if !self.invalid[5] => block_25(self, input, output),
+            _ => self.step(input, output),
+        }
+    }
+}
+
+fn block_0(m: &mut Machine, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {
+    // 0: SetRelativeOffset { source: (Immediate, 30) }
+    m.relative_base = m.relative_base.wrapping_add(30);
+    Exit::Jump(2)
+}
+
+fn block_2(m: &mut Machine, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {
+    // 2: Input { dest_pos: (Position, 40) }
+    let value = match input.take() { Some(v) => v, None => return Exit::AwaitInput(2) };
+    m.memory[40] = value;
+    // 4: JumpIfFalse { bool_param: (Position, 40), jump_dest: (Immediate, 25) }
+    if m.memory[40] == 0 { return Exit::Jump(25); }
+    Exit::Jump(7)
+}
+
+fn block_7(m: &mut Machine, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {
+    // 7: LessThan { left_op: (Position, 40), right_op: (Immediate, 0), dest_pos: (Position, 42) }
+    m.memory[42] = (m.memory[40] < 0) as isize;
+    // 11: JumpIfTrue { bool_param: (Position, 42), jump_dest: (Immediate, 23) }
+    if m.memory[42] != 0 { return Exit::Jump(23); }
+    Exit::Jump(14)
+}
+
+fn block_14(m: &mut Machine, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {
+    // 14: Add { left_op: (Position, 40), right_op: (Position, 41), dest_pos: (Position, 41) }
+    m.memory[41] = m.memory[40] + m.memory[41];
+    // 18: Output { inp_pos: (Relative, 11) }
+    output.push(m.memory[index!(m.relative_base.wrapping_add(11), 18)]);
+    // 20: JumpIfTrue { bool_param: (Immediate, 1), jump_dest: (Immediate, 2) }
+    return Exit::Jump(2);
+    Exit::Jump(23)
+}
+
+fn block_23(m: &mut Machine, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {
+    // 23: Output { inp_pos: (Relative, -100) }
+    output.push(m.memory[index!(m.relative_base.wrapping_add(-100), 23)]);
+    Exit::Jump(25)
+}
+
+fn block_25(m: &mut Machine, input: &mut Option<isize>, output: &mut Vec<isize>) -> Exit {
+    // 25: Add { left_op: (Immediate, 104), right_op: (Immediate, 0), dest_pos: (Position, 29) }
+    if m.write(29, 104 + 0) { return Exit::Jump(29); }
+    // 29: Halt
+    return Exit::Halt(29);
+    Exit::Jump(30)
+}
@@ -0,0 +1,101 @@
+//! Reads text drawn in the 6 pixel high block letters of the puzzle output.
+
+/// Known letters, with `#` for lit pixels. Letters are separated by at least one unlit column,
+/// so a letter's pattern starts and ends with a lit column.
+const FONT: &[(char, [&str; 6])] = &[
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+pub const HEIGHT: usize = 6;
+
+/// Recognise the letters in `pixels`, given as rows of lit (`true`) and unlit pixels. Unlit rows
+/// above and below the text are ignored.
+pub fn read(pixels: &[Vec<bool>]) -> Result<String, String> {
+    let is_blank = |row: &&Vec<bool>| !row.iter().any(|&lit| lit);
+    let first = pixels.iter().position(|row| !is_blank(&row)).unwrap_or(0);
+    let rows: Vec<&Vec<bool>> = pixels[first..].iter().take_while(|row| !is_blank(row)).collect();
+    if rows.len() != HEIGHT {
+        return Err(format!("Expected text {} pixels high, found {}", HEIGHT, rows.len()));
+    }
+
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let lit = |x: usize, y: usize| rows[y].get(x).cloned().unwrap_or(false);
+    let column_lit = |x: usize| (0..HEIGHT).any(|y| lit(x, y));
+
+    let mut text = String::new();
+    let mut x = 0;
+    while x < width {
+        if !column_lit(x) {
+            x += 1;
+            continue;
+        }
+        let start = x;
+        while x < width && column_lit(x) {
+            x += 1;
+        }
+
+        let glyph: Vec<String> = (0..HEIGHT)
+            .map(|y| (start..x).map(|x| if lit(x, y) { '#' } else { '.' }).collect())
+            .collect();
+        let letter = FONT.iter()
+            .find(|(_, pattern)| pattern.iter().zip(&glyph).all(|(a, b)| a == b))
+            .map(|(letter, _)| *letter)
+            .ok_or_else(|| format!("Unrecognised glyph at column {}:\n{}", start, glyph.join("\n")))?;
+        text.push(letter);
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixels(rows: &[&str]) -> Vec<Vec<bool>> {
+        rows.iter().map(|row| row.chars().map(|c| c == '#').collect()).collect()
+    }
+
+    #[test]
+    fn test_read() {
+        let text = pixels(&[
+            "",
+            ".#..#.###..#...#..###..####.",
+            ".#..#.#..#.#...#...#...#....",
+            ".####.#..#..#.#....#...###..",
+            ".#..#.###....#.....#...#....",
+            ".#..#.#.#....#.....#...#....",
+            ".#..#.#..#...#....###..#....",
+            "............................",
+        ]);
+        assert_eq!(read(&text), Ok("HRYIF".to_string()));
+
+        for (letter, pattern) in FONT {
+            assert_eq!(read(&pixels(pattern)), Ok(letter.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_unrecognised() {
+        let text = pixels(&["#..#.", "#..#.", "####.", "#..#.", "#..#.", "#..##"]);
+        assert_eq!(read(&text), Err("Unrecognised glyph at column 0:\n\
+            #..#.\n#..#.\n####.\n#..#.\n#..#.\n#..##".to_string()));
+        assert!(read(&pixels(&["####", "#...", "###.", "#..."])).is_err());
+        assert!(read(&[]).is_err());
+    }
+}
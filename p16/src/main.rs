@@ -77,6 +77,15 @@ impl Image {
     }
 }
 
+impl Image {
+    /// Rows of pixels, lit where they are drawn as `#`.
+    fn lit(&self) -> Vec<Vec<bool>> {
+        self.pixels.chunks(WIDTH)
+            .map(|row| row.iter().map(|pixel| matches!(pixel, PixelValue::BLACK)).collect())
+            .collect()
+    }
+}
+
 impl Display for Image {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         for i in 0..HEIGHT {
@@ -93,6 +102,7 @@ impl Display for Image {
 fn main() -> Result<(), Box<dyn Error + 'static>> {
     let result = run(INPUT)?;
     println!("{}", result);
+    println!("{}", ocr::read(&result.lit())?);
     Ok(())
 }
 
@@ -105,3 +115,33 @@ fn run(input: &[u8]) -> Result<Image, Box<dyn Error + 'static>> {
 
     Ok(Image::from_layers(layers))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lit() {
+        // "HI" drawn on a transparent layer in front of a white one.
+        let text = [
+            "#..#.###",
+            "#..#..#.",
+            "####..#.",
+            "#..#..#.",
+            "#..#..#.",
+            "#..#.###",
+        ];
+        let mut input = Vec::new();
+        for row in &text {
+            input.extend(row.bytes().map(|b| if b == b'#' { b'1' } else { b'2' }));
+            input.extend(std::iter::repeat_n(b'2', WIDTH - row.len()));
+        }
+        input.extend(std::iter::repeat_n(b'0', WIDTH * HEIGHT));
+
+        let image = run(&input).unwrap();
+        assert_eq!(image.to_string().lines().nth(2), Some("####  #                  "));
+        let lit = image.lit();
+        assert_eq!((lit.len(), lit[0].len()), (HEIGHT, WIDTH));
+        assert_eq!(ocr::read(&lit), Ok("HI".to_string()));
+    }
+}
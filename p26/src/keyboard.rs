@@ -0,0 +1,123 @@
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::Read,
+    process::{Command, Stdio},
+};
+
+use crate::{Arcade, Controller, Joystick};
+
+pub const HELP: &str = "a/left: move left, d/right: move right, s/space: stay, u: undo, q: quit";
+
+/// Returned by the keyboard when the player wants to take back the last move.
+#[derive(Debug)]
+pub struct Undo;
+
+impl fmt::Display for Undo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Undo")
+    }
+}
+
+impl Error for Undo {}
+
+/// Reads the joystick position from keypresses. Every key advances the game by one frame.
+pub struct Keyboard<R: Read> {
+    input: R,
+}
+
+impl<R: Read> Keyboard<R> {
+    pub fn new(input: R) -> Self {
+        Keyboard { input }
+    }
+
+    fn key(&mut self) -> Result<Option<u8>, Box<dyn Error + 'static>> {
+        let mut buf = [0u8];
+        match self.input.read(&mut buf)? {
+            0 => Ok(None),
+            _ => Ok(Some(buf[0])),
+        }
+    }
+}
+
+impl<R: Read> Controller for Keyboard<R> {
+    fn joystick(&mut self, arcade: &Arcade) -> Result<Joystick, Box<dyn Error + 'static>> {
+        loop {
+            let key = match self.key()? {
+                Some(key) => key,
+                None => return Err(format!("Input closed at score {}", arcade.screen.score).into()),
+            };
+            match key {
+                b'a' | b'h' => return Ok(Joystick::Left),
+                b'd' | b'l' => return Ok(Joystick::Right),
+                b's' | b'j' | b' ' => return Ok(Joystick::Neutral),
+                b'u' => return Err(Undo.into()),
+                b'q' => return Err(format!("Quit at score {}", arcade.screen.score).into()),
+                // Arrow keys send the escape sequences ESC [ C and ESC [ D.
+                0x1b => {
+                    if self.key()? != Some(b'[') {
+                        continue;
+                    }
+                    match self.key()? {
+                        Some(b'D') => return Ok(Joystick::Left),
+                        Some(b'C') => return Ok(Joystick::Right),
+                        Some(b'B') => return Ok(Joystick::Neutral),
+                        _ => continue,
+                    }
+                }
+                // Ignore line breaks and unknown keys, in case the terminal isn't in raw mode.
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// Puts the terminal into raw mode while it lives, so keypresses are read without waiting for
+/// enter and aren't echoed. Does nothing if there is no terminal.
+pub struct RawMode {
+    enabled: bool,
+}
+
+impl RawMode {
+    pub fn enable() -> Self {
+        RawMode { enabled: stty(&["-icanon", "-echo", "min", "1"]) }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if self.enabled {
+            stty(&["icanon", "echo"]);
+        }
+    }
+}
+
+fn stty(args: &[&str]) -> bool {
+    let tty = match File::open("/dev/tty") {
+        Ok(tty) => tty,
+        Err(_) => return false,
+    };
+    Command::new("stty")
+        .args(args)
+        .stdin(tty)
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        let arcade = Arcade::new(vec![99]);
+        let mut keyboard = Keyboard::new(&b"a\nd \x1b[D\x1b[Cxuq"[..]);
+        let moves: Vec<Joystick> = (0..5).map(|_| keyboard.joystick(&arcade).unwrap()).collect();
+        assert_eq!(moves, vec![Joystick::Left, Joystick::Right, Joystick::Neutral, Joystick::Left, Joystick::Right]);
+        assert!(keyboard.joystick(&arcade).unwrap_err().is::<Undo>());
+        assert!(keyboard.joystick(&arcade).is_err());
+        assert!(keyboard.joystick(&arcade).is_err());
+    }
+}
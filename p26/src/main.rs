@@ -0,0 +1,516 @@
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fs,
+    io,
+};
+
+use intcode::intcode::{Program, ProgramState};
+
+use crate::{
+    keyboard::{Keyboard, RawMode, Undo},
+    replay::{Recording, Replay},
+    screen::{FrameDump, FrameFormat, FrameSink, Screen, Terminal, TileType},
+};
+
+mod keyboard;
+mod replay;
+mod screen;
+
+
+const INPUT: &str = include_str!("../INPUT");
+
+/// Writing this to the first memory cell lets the game run without quarters.
+const FREE_PLAY: isize = 2;
+
+/// Output triples for this position update the segment display instead of a tile.
+const SCORE_POSITION: (isize, isize) = (-1, 0);
+
+const USAGE: &str = "\
+Usage: p26 [--play | --replay FILE] [--record FILE] [--render]
+           [--frames DIR [--format ppm|text] [--scale PIXELS]]
+
+    --play          Play the game by hand with the keyboard instead of the automatic player.
+    --replay FILE   Play the joystick moves recorded in FILE instead of the automatic player.
+    --record FILE   Write the joystick moves of the game to FILE.
+    --render        Draw every frame on the terminal.
+    --frames DIR    Write every frame to a numbered file in DIR.
+    --format FMT    Format of the written frames: 'ppm' images (default) or 'text'.
+    --scale PIXELS  Pixels per tile in PPM frames (default: 8).";
+
+
+#[derive(Debug)]
+struct Options {
+    play: bool,
+    replay: Option<String>,
+    record: Option<String>,
+    render: bool,
+    frames: Option<String>,
+    format: FrameFormat,
+    scale: usize,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item=String>>(mut args: I) -> Result<Self, Box<dyn Error + 'static>> {
+        let mut options = Options {
+            play: false,
+            replay: None,
+            record: None,
+            render: false,
+            frames: None,
+            format: FrameFormat::Ppm,
+            scale: 8,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value for {}\n\n{}", arg, USAGE));
+            match arg.as_str() {
+                "--play" => options.play = true,
+                "--replay" => options.replay = Some(value()?),
+                "--record" => options.record = Some(value()?),
+                "--render" => options.render = true,
+                "--frames" => options.frames = Some(value()?),
+                "--format" => options.format = match value()?.as_str() {
+                    "ppm" => FrameFormat::Ppm,
+                    "text" => FrameFormat::Text,
+                    x => return Err(format!("Unknown frame format '{}'", x).into()),
+                },
+                "--scale" => options.scale = value()?.parse()?,
+                x => return Err(format!("Unknown argument {}\n\n{}", x, USAGE).into()),
+            }
+        }
+        if options.play && options.replay.is_some() {
+            return Err(format!("--play and --replay can't be combined\n\n{}", USAGE).into());
+        }
+        Ok(options)
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error + 'static>> {
+    let options = Options::parse(std::env::args().skip(1))?;
+    let result = run(INPUT, &options)?;
+    eprintln!("{}", result);
+
+    Ok(())
+}
+
+fn run(input: &str, options: &Options) -> Result<isize, Box<dyn Error + 'static>> {
+    let memory: Result<Vec<isize>, _> = input.split(',')
+        .map(|part| part.trim().parse::<isize>())
+        .collect();
+    let mut memory = memory?;
+    memory[0] = FREE_PLAY;
+
+    let mut arcade = Arcade::new(memory);
+    if options.play {
+        arcade.add_sink(Box::new(Terminal::with_footer(io::stdout(), keyboard::HELP)));
+    } else if options.render {
+        arcade.add_sink(Box::new(Terminal::new(io::stdout())));
+    }
+    if let Some(dir) = &options.frames {
+        arcade.add_sink(Box::new(FrameDump::new(dir, options.format, options.scale)?));
+    }
+
+    let result = if options.play {
+        let _raw = RawMode::enable();
+        let mut keyboard = Keyboard::new(io::stdin());
+        arcade.keep_savestates();
+        loop {
+            match arcade.play(&mut keyboard) {
+                Err(err) if err.is::<Undo>() => {
+                    // Go back to the frame before the last move.
+                    let frame = arcade.recording.0.len().saturating_sub(1);
+                    arcade.rewind(frame)?;
+                    arcade.show_frame()?;
+                }
+                result => break result,
+            }
+        }
+    } else if let Some(file) = &options.replay {
+        let mut replay = Replay::new(fs::read_to_string(file)?.parse()?);
+        let result = arcade.play(&mut replay);
+        match replay.remaining() {
+            0 => result,
+            moves => Err(format!("Game ended with {} recorded moves left", moves).into()),
+        }
+    } else {
+        arcade.play(&mut FollowBall)
+    };
+
+    // Keep the recording of lost games too, to reproduce them.
+    if let Some(file) = &options.record {
+        fs::write(file, arcade.recording.to_string())?;
+    }
+    result
+}
+
+struct Arcade {
+    prog: Program,
+    screen: Screen,
+    ball: Option<(isize, isize)>,
+    paddle: Option<(isize, isize)>,
+    /// Outputs of an incomplete triple.
+    pending: Vec<isize>,
+    /// Whether the program waits for the joystick input after the last frame.
+    waiting: bool,
+    recording: Recording,
+    /// State after every frame so far, if enabled with `keep_savestates`.
+    savestates: Option<Vec<Savestate>>,
+    sinks: Vec<Box<dyn FrameSink>>,
+}
+
+/// Everything needed to continue a game from a frame.
+struct Savestate {
+    /// Shares its memory copy-on-write with the running program.
+    prog: Program,
+    screen: Screen,
+    ball: Option<(isize, isize)>,
+    paddle: Option<(isize, isize)>,
+    pending: Vec<isize>,
+    waiting: bool,
+    moves: usize,
+}
+
+impl Arcade {
+    fn new(memory: Vec<isize>) -> Self {
+        Arcade {
+            prog: Program::new(memory),
+            screen: Screen::default(),
+            ball: None,
+            paddle: None,
+            pending: Vec::new(),
+            waiting: false,
+            recording: Recording::default(),
+            savestates: None,
+            sinks: Vec::new(),
+        }
+    }
+
+    /// Keep a savestate of every frame, so the game can be rewound.
+    fn keep_savestates(&mut self) {
+        self.savestates.get_or_insert_with(Vec::new);
+    }
+
+    fn save(&self) -> Result<Savestate, Box<dyn Error + 'static>> {
+        Ok(Savestate {
+            prog: self.prog.fork()?,
+            screen: self.screen.clone(),
+            ball: self.ball,
+            paddle: self.paddle,
+            pending: self.pending.clone(),
+            waiting: self.waiting,
+            moves: self.recording.0.len(),
+        })
+    }
+
+    /// Continue from `state`. The recording is cut back to the moves made before it, so `state`
+    /// has to come from this game.
+    fn load(&mut self, state: &Savestate) -> Result<(), Box<dyn Error + 'static>> {
+        self.prog = state.prog.fork()?;
+        self.screen = state.screen.clone();
+        self.ball = state.ball;
+        self.paddle = state.paddle;
+        self.pending = state.pending.clone();
+        self.waiting = state.waiting;
+        self.recording.0.truncate(state.moves);
+        Ok(())
+    }
+
+    /// Go back to the state after `frame`, the first frame being 0, and forget the later frames.
+    fn rewind(&mut self, frame: usize) -> Result<(), Box<dyn Error + 'static>> {
+        let mut savestates = self.savestates.take().ok_or("Savestates are not kept")?;
+        let result = if frame < savestates.len() {
+            savestates.truncate(frame + 1);
+            self.load(&savestates[frame])
+        } else {
+            Err(format!("No savestate for frame {} of {}", frame, savestates.len()).into())
+        };
+        self.savestates = Some(savestates);
+        result
+    }
+
+    /// Show every frame on `sink`.
+    fn add_sink(&mut self, sink: Box<dyn FrameSink>) {
+        self.sinks.push(sink);
+    }
+
+    fn blocks(&self) -> usize {
+        self.screen.count(TileType::Block)
+    }
+
+    fn show_frame(&mut self) -> io::Result<()> {
+        for sink in &mut self.sinks {
+            sink.frame(&self.screen)?;
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, output: &[isize]) -> Result<(), Box<dyn Error + 'static>> {
+        self.pending.extend_from_slice(output);
+        for triple in self.pending.chunks_exact(3) {
+            let (position, value) = ((triple[0], triple[1]), triple[2]);
+            if position == SCORE_POSITION {
+                self.screen.score = value;
+                continue;
+            }
+
+            let tile = TileType::try_from(value)?;
+            match tile {
+                TileType::Ball => self.ball = Some(position),
+                TileType::Paddle => self.paddle = Some(position),
+                _ => (),
+            }
+            self.screen.set(position.0, position.1, tile)?;
+        }
+        let complete = self.pending.len() - self.pending.len() % 3;
+        self.pending.drain(..complete);
+        Ok(())
+    }
+
+    /// Run the program until it waits for input or halts, then show the frame.
+    fn advance(&mut self, mut input: Option<isize>) -> Result<(), Box<dyn Error + 'static>> {
+        let (state, out) = self.prog.run(&mut input);
+        if state == ProgramState::Crash {
+            let report = self.prog.crash_report().expect("crashed program has a report");
+            return Err(format!("The game crashed\n{}", report).into());
+        }
+        self.update(&out)?;
+        self.waiting = state == ProgramState::AwaitInput;
+        self.show_frame()?;
+        if self.savestates.is_some() {
+            let state = self.save()?;
+            if let Some(savestates) = &mut self.savestates {
+                savestates.push(state);
+            }
+        }
+        Ok(())
+    }
+
+    /// Play until the game ends, asking `controller` for the joystick position whenever the game
+    /// waits for input. Continues a loaded or rewound game from its last frame. Returns the final
+    /// score if all blocks were destroyed, the crash report if the program crashed.
+    fn play(&mut self, controller: &mut dyn Controller) -> Result<isize, Box<dyn Error + 'static>> {
+        if !self.waiting {
+            self.advance(None)?;
+        }
+        while self.waiting {
+            let joystick = controller.joystick(self)?;
+            self.recording.0.push(joystick);
+            self.advance(Some(joystick as isize))?;
+        }
+
+        match self.blocks() {
+            0 => Ok(self.screen.score),
+            blocks => Err(format!("Game over with {} blocks left at score {}", blocks, self.screen.score).into()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Joystick {
+    Left = -1,
+    Neutral = 0,
+    Right = 1,
+}
+
+impl TryFrom<isize> for Joystick {
+    type Error = String;
+
+    fn try_from(v: isize) -> Result<Self, Self::Error> {
+        match v {
+            -1 => Ok(Joystick::Left),
+            0 => Ok(Joystick::Neutral),
+            1 => Ok(Joystick::Right),
+            x => Err(format!("Unknown joystick position {}", x)),
+        }
+    }
+}
+
+trait Controller {
+    fn joystick(&mut self, arcade: &Arcade) -> Result<Joystick, Box<dyn Error + 'static>>;
+}
+
+/// Keeps the paddle below the ball.
+struct FollowBall;
+
+impl Controller for FollowBall {
+    fn joystick(&mut self, arcade: &Arcade) -> Result<Joystick, Box<dyn Error + 'static>> {
+        Ok(match (arcade.ball, arcade.paddle) {
+            (Some((ball, _)), Some((paddle, _))) if ball < paddle => Joystick::Left,
+            (Some((ball, _)), Some((paddle, _))) if ball > paddle => Joystick::Right,
+            _ => Joystick::Neutral,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// A tiny breakout on a 5x6 field with three blocks worth 10 points each. The game halts
+    /// when all blocks are gone or the ball passes the paddle.
+    const BREAKOUT: &str = "\
+    109,864,21101,9,0,0,1105,1,181,99,204,1,204,2,204,3,1101,0,0,854,2106,0,0,
+    21208,1,2,2,1206,2,37,1001,861,0,854,2106,0,0,21208,1,3,2,1206,2,51,1001,
+    862,0,854,2106,0,0,21208,1,4,2,1206,2,65,1001,863,0,854,2106,0,0,1101,0,0,
+    854,2106,0,0,1101,0,0,854,2106,0,0,21208,1,2,2,1206,2,90,1101,0,0,861,
+    21208,1,3,2,1206,2,101,1101,0,0,862,21208,1,4,2,1206,2,112,1101,0,0,863,
+    21201,1,0,4,21101,1,0,5,21101,0,0,6,21101,133,0,3,109,3,1105,1,10,109,-3,
+    21001,854,0,2,21001,860,10,2,1201,2,0,860,21101,-1,0,4,21101,0,0,5,21001,
+    860,0,6,21101,168,0,3,109,3,1105,1,10,109,-3,21001,854,0,2,1101,0,0,854,
+    2106,0,0,21101,0,0,1,21207,1,7,2,1206,2,257,21101,0,0,10,21201,1,0,11,
+    21101,1,0,12,21101,213,0,9,109,9,1105,1,10,109,-9,21001,854,0,2,21101,6,0,
+    10,21201,1,0,11,21101,1,0,12,21101,240,0,9,109,9,1105,1,10,109,-9,21001,
+    854,0,2,21201,1,1,2,21201,2,0,1,1105,1,185,21101,1,0,2,21207,2,6,3,1206,
+    3,306,21201,2,0,10,21101,0,0,11,21101,1,0,12,21101,289,0,9,109,9,1105,1,
+    10,109,-9,21001,854,0,3,21201,2,1,3,21201,3,0,2,1105,1,261,21101,2,0,10,
+    21101,1,0,11,21101,2,0,12,21101,327,0,9,109,9,1105,1,10,109,-9,21001,854,0,
+    3,21101,3,0,10,21101,1,0,11,21101,2,0,12,21101,354,0,9,109,9,1105,1,10,
+    109,-9,21001,854,0,3,21101,4,0,10,21101,1,0,11,21101,2,0,12,21101,381,0,9,
+    109,9,1105,1,10,109,-9,21001,854,0,3,21001,859,0,10,21101,6,0,11,21101,3,0,
+    12,21101,408,0,9,109,9,1105,1,10,109,-9,21001,854,0,3,21001,855,0,10,21001,
+    856,0,11,21101,4,0,12,21101,435,0,9,109,9,1105,1,10,109,-9,21001,854,0,3,
+    21101,-1,0,10,21101,0,0,11,21001,860,0,12,21101,462,0,9,109,9,1105,1,10,
+    109,-9,21001,854,0,3,20001,861,862,3,20201,3,863,4,22107,0,4,5,1206,5,847,
+    203,3,22001,859,3,4,21201,4,0,3,21101,0,0,4,21207,3,1,5,21208,5,0,5,1206,
+    5,524,22107,5,3,6,21208,6,0,6,21208,6,0,4,21208,4,0,4,1206,4,585,21001,
+    859,0,10,21101,6,0,11,21101,0,0,12,21101,548,0,9,109,9,1105,1,10,109,-9,
+    21001,854,0,4,1201,3,0,859,21001,859,0,10,21101,6,0,11,21101,3,0,12,21101,
+    579,0,9,109,9,1105,1,10,109,-9,21001,854,0,4,21101,0,0,4,21008,856,5,5,
+    1206,5,608,21008,858,1,6,21208,6,0,4,21208,4,0,4,1206,4,633,20008,859,855,
+    4,21208,4,0,4,1206,4,629,1101,0,0,854,2106,0,0,1101,-1,0,858,20001,855,857,
+    4,21201,4,0,4,21101,1,0,5,21207,4,1,6,1205,6,664,22107,5,4,7,21208,7,0,5,
+    21208,5,0,5,1206,5,683,21002,857,-1,5,1201,5,0,857,20001,855,857,5,21201,5,
+    0,4,20001,856,858,5,21201,5,0,5,21207,5,1,6,1206,6,710,1101,1,0,858,20001,
+    856,858,6,21201,6,0,5,21101,0,0,6,21208,5,1,7,1206,7,748,21201,4,0,10,
+    21101,734,0,9,109,9,1105,1,23,109,-9,21001,854,0,8,21208,8,0,6,21208,6,0,
+    6,1206,6,782,21201,4,0,10,21101,764,0,9,109,9,1105,1,79,109,-9,21001,854,0,
+    6,21002,858,-1,6,1201,6,0,858,21001,856,0,5,21001,855,0,10,21001,856,0,11,
+    21101,0,0,12,21101,803,0,9,109,9,1105,1,10,109,-9,21001,854,0,6,1201,4,0,
+    855,1201,5,0,856,21001,855,0,10,21001,856,0,11,21101,4,0,12,21101,838,0,9,
+    109,9,1105,1,10,109,-9,21001,854,0,6,1105,1,468,1101,0,0,854,2106,0,0,0,2,
+    3,1,1,2,0,1,1,1";
+
+    fn game() -> Arcade {
+        let memory = BREAKOUT.split(',').map(|part| part.trim().parse().unwrap()).collect();
+        Arcade::new(memory)
+    }
+
+    #[test]
+    fn test_follow_ball() {
+        assert_eq!(game().play(&mut FollowBall).unwrap(), 30);
+    }
+
+    #[test]
+    fn test_game_over() {
+        struct Idle;
+        impl Controller for Idle {
+            fn joystick(&mut self, _: &Arcade) -> Result<Joystick, Box<dyn Error + 'static>> {
+                Ok(Joystick::Neutral)
+            }
+        }
+        let mut arcade = game();
+        assert!(arcade.play(&mut Idle).is_err());
+        assert!(arcade.blocks() > 0);
+    }
+
+    /// Keeps all frames as text.
+    struct Frames(Rc<RefCell<Vec<String>>>);
+
+    impl FrameSink for Frames {
+        fn frame(&mut self, screen: &Screen) -> io::Result<()> {
+            self.0.borrow_mut().push(screen.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_frames() {
+        let frames = Rc::new(RefCell::new(Vec::new()));
+        let mut arcade = game();
+        arcade.add_sink(Box::new(Frames(frames.clone())));
+        arcade.play(&mut FollowBall).unwrap();
+
+        let frames = frames.borrow();
+        assert_eq!(frames.len(), 21);
+        assert_eq!(frames[0], "\
+#######
+# === #
+#     #
+# o   #
+#     #
+#     #
+# -   #
+Score: 0
+");
+        assert!(frames[20].ends_with("#     #\n#    -#\nScore: 30\n"));
+
+        let ppm = arcade.screen.ppm(2);
+        assert!(ppm.starts_with(b"P6\n14 14\n255\n"));
+        assert_eq!(ppm.len(), "P6\n14 14\n255\n".len() + 14 * 14 * 3);
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut arcade = game();
+        arcade.play(&mut FollowBall).unwrap();
+        let recording: Recording = arcade.recording.to_string().parse().unwrap();
+        assert_eq!(recording.0.len(), 20);
+
+        let mut replay = Replay::new(recording.clone());
+        let mut replayed = game();
+        assert_eq!(replayed.play(&mut replay).unwrap(), 30);
+        assert_eq!(replay.remaining(), 0);
+        assert_eq!(replayed.recording, recording);
+
+        let mut short = Recording(recording.0[..10].to_vec());
+        assert!(game().play(&mut Replay::new(short.clone())).is_err());
+        short.0[0] = Joystick::Left;
+        assert!(game().play(&mut Replay::new(short)).is_err());
+    }
+
+    #[test]
+    fn test_rewind() {
+        let frames = Rc::new(RefCell::new(Vec::new()));
+        let mut arcade = game();
+        arcade.keep_savestates();
+        arcade.add_sink(Box::new(Frames(frames.clone())));
+        assert_eq!(arcade.play(&mut FollowBall).unwrap(), 30);
+        let recording = arcade.recording.clone();
+        assert!(arcade.rewind(21).is_err());
+
+        // Continuing from an earlier frame repeats the same moves and frames.
+        arcade.rewind(7).unwrap();
+        assert_eq!(arcade.recording.0, recording.0[..7]);
+        assert_eq!(arcade.screen.to_string(), frames.borrow()[7]);
+        assert_eq!(arcade.play(&mut FollowBall).unwrap(), 30);
+        assert_eq!(arcade.recording, recording);
+        assert_eq!(frames.borrow()[8..21], frames.borrow()[21..]);
+
+        // A savestate restores the game even after the program halted.
+        let savestates = arcade.savestates.take().unwrap();
+        arcade.load(&savestates[3]).unwrap();
+        assert_eq!(arcade.screen.to_string(), frames.borrow()[3]);
+        assert_eq!(arcade.play(&mut FollowBall).unwrap(), 30);
+        assert!(game().rewind(0).is_err());
+    }
+
+    #[test]
+    fn test_crash() {
+        // Reads the joystick, then jumps into empty memory.
+        let mut arcade = Arcade::new(vec![3, 10, 1105, 1, 20]);
+        let err = arcade.play(&mut FollowBall).unwrap_err().to_string();
+        assert!(err.starts_with("The game crashed\nCrash at 20 after 2 instructions: Unknown instruction 0\n"), "{}", err);
+        assert_eq!(arcade.recording.0, vec![Joystick::Neutral]);
+    }
+
+    #[test]
+    fn test_score_display() {
+        let mut arcade = game();
+        arcade.update(&[-1, 0, 12345, 3, 5]).unwrap();
+        assert_eq!(arcade.screen.score, 12345);
+        arcade.update(&[4]).unwrap();
+        assert_eq!(arcade.ball, Some((3, 5)));
+        assert!(arcade.update(&[1, 1, 7]).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt,
+    str::FromStr,
+};
+
+use crate::{Arcade, Controller, Joystick};
+
+/// Joystick inputs of a game, one per frame. Written as comma separated -1, 0 and 1 like the
+/// values the game reads.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Recording(pub Vec<Joystick>);
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let moves: Vec<String> = self.0.iter().map(|&joystick| (joystick as isize).to_string()).collect();
+        writeln!(f, "{}", moves.join(","))
+    }
+}
+
+impl FromStr for Recording {
+    type Err = Box<dyn Error + 'static>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| Ok(Joystick::try_from(part.parse::<isize>()?)?))
+            .collect::<Result<_, Self::Err>>()
+            .map(Recording)
+    }
+}
+
+/// Plays back a recording. Fails if the game asks for more moves than were recorded.
+pub struct Replay {
+    moves: Vec<Joystick>,
+    next: usize,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Replay { moves: recording.0, next: 0 }
+    }
+
+    /// Moves that haven't been played yet.
+    pub fn remaining(&self) -> usize {
+        self.moves.len() - self.next
+    }
+}
+
+impl Controller for Replay {
+    fn joystick(&mut self, _: &Arcade) -> Result<Joystick, Box<dyn Error + 'static>> {
+        let joystick = self.moves.get(self.next)
+            .ok_or_else(|| format!("Recording ended after {} moves", self.moves.len()))?;
+        self.next += 1;
+        Ok(*joystick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording() {
+        let recording: Recording = "-1,0,1,\n1\n".parse().unwrap();
+        assert_eq!(recording.0, vec![Joystick::Left, Joystick::Neutral, Joystick::Right, Joystick::Right]);
+        assert_eq!(recording.to_string(), "-1,0,1,1\n");
+        assert_eq!("".parse::<Recording>().unwrap(), Recording::default());
+        assert!("0,2".parse::<Recording>().is_err());
+        assert!("0,x".parse::<Recording>().is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    convert::TryFrom,
+    fmt,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TileType {
+    Empty,
+    Wall,
+    Block,
+    Paddle,
+    Ball,
+}
+
+impl TileType {
+    fn glyph(self) -> char {
+        use TileType::*;
+        match self {
+            Empty => ' ',
+            Wall => '#',
+            Block => '=',
+            Paddle => '-',
+            Ball => 'o',
+        }
+    }
+
+    fn color(self) -> [u8; 3] {
+        use TileType::*;
+        match self {
+            Empty => [0, 0, 0],
+            Wall => [128, 128, 128],
+            Block => [70, 130, 220],
+            Paddle => [230, 230, 230],
+            Ball => [255, 140, 0],
+        }
+    }
+}
+
+impl TryFrom<isize> for TileType {
+    type Error = String;
+
+    fn try_from(v: isize) -> Result<Self, Self::Error> {
+        use TileType::*;
+        match v {
+            0 => Ok(Empty),
+            1 => Ok(Wall),
+            2 => Ok(Block),
+            3 => Ok(Paddle),
+            4 => Ok(Ball),
+            x => Err(format!("Unknown tile type {}", x)),
+        }
+    }
+}
+
+/// Framebuffer of the arcade cabinet, grows with the tiles drawn to it.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    tiles: Vec<TileType>,
+    pub score: isize,
+}
+
+impl Screen {
+    pub fn set(&mut self, x: isize, y: isize, tile: TileType) -> Result<(), String> {
+        if x < 0 || y < 0 {
+            return Err(format!("Tile position ({}, {}) is outside of the screen", x, y));
+        }
+        let (x, y) = (x as usize, y as usize);
+        if x >= self.width || y >= self.height {
+            let width = self.width.max(x + 1);
+            let height = self.height.max(y + 1);
+            let mut tiles = vec![TileType::Empty; width * height];
+            for (row, line) in self.tiles.chunks(self.width.max(1)).enumerate() {
+                tiles[row * width..row * width + line.len()].copy_from_slice(line);
+            }
+            self.width = width;
+            self.height = height;
+            self.tiles = tiles;
+        }
+        self.tiles[y * self.width + x] = tile;
+        Ok(())
+    }
+
+    pub fn count(&self, tile: TileType) -> usize {
+        self.tiles.iter().filter(|t| **t == tile).count()
+    }
+
+    /// Binary PPM image with `scale` pixels per tile. The score is not part of the image.
+    pub fn ppm(&self, scale: usize) -> Vec<u8> {
+        let scale = scale.max(1);
+        let mut image = format!("P6\n{} {}\n255\n", self.width * scale, self.height * scale).into_bytes();
+        for line in self.tiles.chunks(self.width.max(1)) {
+            let mut row = Vec::with_capacity(line.len() * scale * 3);
+            for tile in line {
+                for _ in 0..scale {
+                    row.extend_from_slice(&tile.color());
+                }
+            }
+            for _ in 0..scale {
+                image.extend_from_slice(&row);
+            }
+        }
+        image
+    }
+}
+
+/// Text frame: one glyph per tile followed by the score line.
+impl fmt::Display for Screen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.tiles.chunks(self.width.max(1)) {
+            let line: String = line.iter().map(|tile| tile.glyph()).collect();
+            writeln!(f, "{}", line)?;
+        }
+        writeln!(f, "Score: {}", self.score)
+    }
+}
+
+/// Receives the screen whenever the game waits for the next joystick input and once at the end.
+pub trait FrameSink {
+    fn frame(&mut self, screen: &Screen) -> io::Result<()>;
+}
+
+/// Redraws every frame on the terminal.
+pub struct Terminal<W: Write> {
+    out: W,
+    /// Text shown below every frame.
+    footer: String,
+}
+
+impl<W: Write> Terminal<W> {
+    pub fn new(out: W) -> Self {
+        Terminal { out, footer: String::new() }
+    }
+
+    pub fn with_footer(out: W, footer: &str) -> Self {
+        Terminal { out, footer: format!("{}\n", footer) }
+    }
+}
+
+impl<W: Write> FrameSink for Terminal<W> {
+    fn frame(&mut self, screen: &Screen) -> io::Result<()> {
+        // Move the cursor home and clear the terminal before drawing.
+        write!(self.out, "\x1b[H\x1b[2J{}{}", screen, self.footer)?;
+        self.out.flush()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FrameFormat {
+    Ppm,
+    Text,
+}
+
+/// Writes every frame to its own numbered file in a directory.
+pub struct FrameDump {
+    dir: PathBuf,
+    format: FrameFormat,
+    /// Pixels per tile of PPM frames.
+    scale: usize,
+    frames: usize,
+}
+
+impl FrameDump {
+    pub fn new<P: Into<PathBuf>>(dir: P, format: FrameFormat, scale: usize) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(FrameDump { dir, format, scale, frames: 0 })
+    }
+}
+
+impl FrameSink for FrameDump {
+    fn frame(&mut self, screen: &Screen) -> io::Result<()> {
+        let (extension, content) = match self.format {
+            FrameFormat::Ppm => ("ppm", screen.ppm(self.scale)),
+            FrameFormat::Text => ("txt", screen.to_string().into_bytes()),
+        };
+        fs::write(self.dir.join(format!("frame_{:05}.{}", self.frames, extension)), content)?;
+        self.frames += 1;
+        Ok(())
+    }
+}